@@ -0,0 +1,94 @@
+#!/usr/bin/env python3
+"""Generates data/books.tsv, the index of the chapters and headings searched by `!book`,
+`!reference`, `!nomicon`, `!rbe` and `!async`.
+
+Reads the books installed with the `rust-docs` rustup component, and the Async Book from
+the mdBook output of its repository since it is not part of `rust-docs`:
+
+    rustup component add rust-docs --toolchain nightly
+    git clone https://github.com/rust-lang/async-book && mdbook build async-book
+    ASYNC_BOOK=async-book/book python3 scripts/generate_book_index.py [path to share/doc/rust/html]
+
+Every line has the book, the chapter title, the heading, its level (1 for the chapter
+itself) and the page path in the book with the heading anchor, separated by tabs.
+"""
+import html
+import os
+import re
+import subprocess
+import sys
+
+# directory of each book in the docs, also the book name used by the bot
+BOOKS = ["book", "reference", "nomicon", "rust-by-example"]
+# name of the Async Book, whose mdBook output is given with the ASYNC_BOOK variable
+ASYNC_BOOK = "async-book"
+SKIPPED_PAGES = {"print.html", "toc.html", "404.html", "SUMMARY.html", "index.html"}
+# pages kept for the links of older editions, which only point to the current book
+SKIPPED_DIRECTORIES = {"2018-edition", "first-edition", "second-edition"}
+
+HEADING = re.compile(r"<h([1-4]) id=\"([^\"]+)\"[^>]*>(.*?)</h\1>", re.S)
+TITLE = re.compile(r"<title>(.*?)</title>", re.S)
+TAG = re.compile(r"<[^>]+>")
+
+
+def text(fragment):
+    return " ".join(html.unescape(TAG.sub("", fragment)).split())
+
+
+def page_sections(book, book_root, relative):
+    with open(os.path.join(book_root, relative), encoding="utf-8") as page:
+        content = page.read()
+    main = content.find("<main")
+    if main < 0:
+        return []
+    title = TITLE.search(content)
+    chapter = text(title.group(1)).rsplit(" - ", 1)[0] if title else ""
+    sections = []
+    for index, heading in enumerate(HEADING.finditer(content, main)):
+        level, anchor, name = heading.groups()
+        name = text(name)
+        if not name:
+            continue
+        url_path = relative if index == 0 else "{}#{}".format(relative, anchor)
+        # the first heading is the chapter itself
+        level = "1" if index == 0 else level
+        sections.append((book, chapter or name, name, level, url_path))
+    return sections
+
+
+def main():
+    root = sys.argv[1] if len(sys.argv) > 1 else os.path.expanduser(
+        "~/.rustup/toolchains/nightly-x86_64-unknown-linux-gnu/share/doc/rust/html"
+    )
+    async_book_root = os.environ.get("ASYNC_BOOK")
+    if not async_book_root:
+        sys.exit("ASYNC_BOOK should be the directory built by `mdbook build` in the Async Book repository")
+    books = [(book, os.path.join(root, book)) for book in BOOKS]
+    books.append((ASYNC_BOOK, os.path.expanduser(async_book_root)))
+    sections = []
+    for book, book_root in books:
+        for directory, subdirectories, files in os.walk(book_root):
+            subdirectories[:] = sorted(
+                d for d in subdirectories if d not in SKIPPED_DIRECTORIES
+            )
+            for file_name in sorted(files):
+                if not file_name.endswith(".html") or file_name in SKIPPED_PAGES:
+                    continue
+                relative = os.path.relpath(os.path.join(directory, file_name), book_root)
+                sections.extend(page_sections(book, book_root, relative))
+
+    version = subprocess.run(
+        ["rustc", "+nightly", "--version"], capture_output=True, text=True
+    ).stdout.strip()
+    output = os.path.join(os.path.dirname(__file__), "..", "data", "books.tsv")
+    with open(output, "w", encoding="utf-8") as index:
+        index.write(
+            "# generated by scripts/generate_book_index.py from the docs of {} and the Async Book\n".format(version)
+        )
+        for section in sections:
+            index.write("\t".join(field.replace("\t", " ") for field in section) + "\n")
+    print("{} sections written to {}".format(len(sections), os.path.normpath(output)))
+
+
+if __name__ == "__main__":
+    main()
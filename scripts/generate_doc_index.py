@@ -0,0 +1,154 @@
+#!/usr/bin/env python3
+"""Generates data/std_docs.tsv, the index of the std, core and alloc items used by `!doc`.
+
+Reads the HTML docs installed with the `rust-docs` rustup component:
+
+    rustup component add rust-docs --toolchain nightly
+    python3 scripts/generate_doc_index.py [path to share/doc/rust/html]
+
+Every line has the item path, kind, stability, docs URL path, signature and first paragraph
+of docs, separated by tabs. Newlines and tabs in the signature are escaped as \\n and \\t.
+"""
+import html
+import os
+import re
+import subprocess
+import sys
+
+CRATES = ["std", "core", "alloc"]
+KINDS = [
+    "struct", "enum", "trait", "fn", "macro", "type", "constant", "static", "union",
+    "primitive", "keyword", "attr", "derive", "traitalias",
+]
+# modules with thousands of platform specific items
+SKIPPED_MODULES = {"arch", "intrinsics"}
+MAX_SIGNATURE = 400
+MAX_SUMMARY = 400
+
+TAG = re.compile(r"<[^>]+>")
+SPACES = re.compile(r"[ \t]+")
+
+
+def text(fragment):
+    fragment = re.sub(r"<div class=\"where\">", "\n", fragment)
+    fragment = re.sub(r"<br\s*/?>", "\n", fragment)
+    value = html.unescape(TAG.sub("", fragment))
+    lines = [SPACES.sub(" ", line).rstrip() for line in value.split("\n")]
+    return "\n".join(line for line in lines if line.strip()).strip()
+
+
+def clip(value, limit):
+    return value if len(value) <= limit else value[: limit - 3].rstrip() + "..."
+
+
+def first_paragraph(fragment):
+    match = re.search(r"<div class=\"docblock[^\"]*\">\s*<p>(.*?)</p>", fragment, re.S)
+    return clip(" ".join(text(match.group(1)).split()), MAX_SUMMARY) if match else ""
+
+
+def stability(fragment):
+    """Stability of the first item of `fragment`"""
+    if re.search(r"class=\"stab deprecated\"", fragment):
+        return "deprecated"
+    unstable = re.search(r"class=\"stab unstable\">.*?<code>([^<]+)</code>", fragment, re.S)
+    if unstable:
+        return "unstable:" + unstable.group(1)
+    since = re.search(r"<span class=\"since\" title=\"Stable since Rust version ([^\",]+)", fragment)
+    return "stable:" + since.group(1) if since else ""
+
+
+def escape(value):
+    return value.replace("\\", "\\\\").replace("\t", "\\t").replace("\n", "\\n")
+
+
+def page_items(root, relative):
+    crate_path, file_name = os.path.split(relative)
+    kind, name = re.match(r"([a-z]+)\.(.+)\.html$", file_name).groups()
+    with open(os.path.join(root, relative), encoding="utf-8") as page:
+        content = page.read()
+    decl = re.search(r"<pre class=\"rust item-decl\"><code>(.*?)</code></pre>", content, re.S)
+    if decl is None and kind not in ("primitive", "keyword"):
+        # re-export redirect
+        return []
+    modules = crate_path.split(os.sep)
+    path = "::".join(modules + [name])
+    main = content.find("<section id=\"main-content\"")
+    top_end = content.find("<h2", main)
+    top = content[main:top_end if top_end > 0 else len(content)]
+    signature = clip(text(decl.group(1)), MAX_SIGNATURE) if decl else ""
+    items = [(path, kind, stability(top), relative, signature, first_paragraph(top))]
+    if kind not in ("struct", "enum", "trait", "primitive", "union"):
+        return items
+
+    # inherent methods, and the methods declared by traits
+    ends = [
+        content.find(marker)
+        for marker in (
+            "id=\"deref-methods",
+            "id=\"trait-implementations\"",
+            "id=\"synthetic-implementations\"",
+            "id=\"implementors\"",
+            "id=\"foreign-impls\"",
+            "id=\"object-safety\"",
+            "id=\"dyn-compatibility\"",
+        )
+    ]
+    end = min([position for position in ends if position > 0] or [len(content)])
+    methods = content[top_end:end]
+    starts = [m for m in re.finditer(r"<section id=\"(tymethod|method)\.([A-Za-z0-9_]+)\" class=\"method\"", methods)]
+    seen = set()
+    for index, start in enumerate(starts):
+        anchor_kind, method = start.groups()
+        if method in seen:
+            continue
+        seen.add(method)
+        stop = starts[index + 1].start() if index + 1 < len(starts) else len(methods)
+        section = methods[start.start():stop]
+        header = re.search(r"<h4 class=\"code-header\">(.*?)</h4>", section, re.S)
+        items.append((
+            path + "::" + method,
+            "method",
+            stability(section),
+            "{}#{}.{}".format(relative, anchor_kind, method),
+            clip(text(header.group(1)), MAX_SIGNATURE) if header else "",
+            first_paragraph(section),
+        ))
+    return items
+
+
+def main():
+    root = sys.argv[1] if len(sys.argv) > 1 else os.path.expanduser(
+        "~/.rustup/toolchains/nightly-x86_64-unknown-linux-gnu/share/doc/rust/html"
+    )
+    page_name = re.compile(r"^(%s)\..+\.html$" % "|".join(KINDS))
+    items = []
+    std_paths = set()
+    for crate in CRATES:
+        for directory, subdirectories, files in os.walk(os.path.join(root, crate)):
+            subdirectories[:] = sorted(d for d in subdirectories if d not in SKIPPED_MODULES)
+            for file_name in sorted(files):
+                if not page_name.match(file_name):
+                    continue
+                relative = os.path.relpath(os.path.join(directory, file_name), root)
+                for item in page_items(root, relative):
+                    unprefixed = item[0].split("::", 1)[-1]
+                    if crate == "std":
+                        std_paths.add(unprefixed)
+                    elif unprefixed in std_paths:
+                        # re-exported by std, keep the std path
+                        continue
+                    items.append(item)
+
+    version = subprocess.run(
+        ["rustc", "+nightly", "--version"], capture_output=True, text=True
+    ).stdout.strip()
+    output = os.path.join(os.path.dirname(__file__), "..", "data", "std_docs.tsv")
+    with open(output, "w", encoding="utf-8") as index:
+        index.write("# generated by scripts/generate_doc_index.py from the docs of {}\n".format(version))
+        for item in items:
+            index.write("\t".join(escape(field) for field in item) + "\n")
+    print("{} items written to {}".format(len(items), os.path.normpath(output)))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,258 @@
+/// Chapters and headings of the books, generated by `scripts/generate_book_index.py`
+const BUNDLED_INDEX: &str = include_str!("../data/books.tsv");
+
+/// Sections listed for a search
+pub const MAX_RESULTS: usize = 5;
+
+/// A book searched by a command of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Book {
+    /// The command searching the book, e.g. `book` for `!book`
+    pub command: &'static str,
+    /// The book's directory in the docs or its own name, used in the index
+    pub id: &'static str,
+    pub title: &'static str,
+    pub url: &'static str,
+}
+
+/// The books shipped with the `rust-docs` component, then the Async Book which is indexed
+/// from its own mdBook output
+pub const BOOKS: &[Book] = &[
+    Book {
+        command: "book",
+        id: "book",
+        title: "The Rust Programming Language",
+        url: "https://doc.rust-lang.org/book/",
+    },
+    Book {
+        command: "reference",
+        id: "reference",
+        title: "The Rust Reference",
+        url: "https://doc.rust-lang.org/reference/",
+    },
+    Book {
+        command: "nomicon",
+        id: "nomicon",
+        title: "The Rustonomicon",
+        url: "https://doc.rust-lang.org/nomicon/",
+    },
+    Book {
+        command: "rbe",
+        id: "rust-by-example",
+        title: "Rust by Example",
+        url: "https://doc.rust-lang.org/rust-by-example/",
+    },
+    Book {
+        command: "async",
+        id: "async-book",
+        title: "Asynchronous Programming in Rust",
+        url: "https://rust-lang.github.io/async-book/",
+    },
+];
+
+pub fn find_book(command: &str) -> Option<&'static Book> {
+    BOOKS.iter().find(|book| book.command == command)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub book: &'static Book,
+    pub chapter: String,
+    pub heading: String,
+    /// 1 for the chapter itself, then the level of the heading
+    level: u8,
+    /// Path of the page in the book, with the anchor of the heading
+    url_path: String,
+}
+
+impl Section {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let id = fields.next()?;
+        Some(Self {
+            book: BOOKS.iter().find(|book| book.id == id)?,
+            chapter: fields.next()?.to_owned(),
+            heading: fields.next()?.to_owned(),
+            level: fields.next()?.parse().ok()?,
+            url_path: fields.next()?.to_owned(),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("{}{}", self.book.url, self.url_path)
+    }
+
+    pub fn is_chapter(&self) -> bool {
+        self.level == 1
+    }
+}
+
+/// The sections of the books, searched by their headings
+#[derive(Debug, Clone, Default)]
+pub struct BookIndex {
+    sections: Vec<Section>,
+}
+
+impl BookIndex {
+    pub fn parse(index: &str) -> Self {
+        Self {
+            sections: index
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(Section::parse)
+                .collect(),
+        }
+    }
+
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_INDEX)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// The sections of `book` whose heading matches the words of `query`, best first. Every
+    /// word must be in the heading or in the chapter title, and headings matching more words
+    /// come first, then chapters and shorter headings.
+    pub fn search(&self, book: &Book, query: &str) -> Vec<&Section> {
+        let terms = words(query);
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let mut results: Vec<(usize, usize, usize, &Section)> = self
+            .sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| section.book == book)
+            .filter_map(|(position, section)| {
+                let heading = words(&section.heading);
+                let chapter = words(&section.chapter);
+                let mut score = 0;
+                for term in &terms {
+                    if heading.contains(term) {
+                        score += 3;
+                    } else if chapter.contains(term) {
+                        score += 1;
+                    } else {
+                        return None;
+                    }
+                }
+                if score < 3 {
+                    // only the chapter matches, its own entry is enough
+                    return None;
+                }
+                if section.is_chapter() {
+                    score += 2;
+                }
+                Some((score, heading.len(), position, section))
+            })
+            .collect();
+        results.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        results
+            .into_iter()
+            .map(|(_, _, _, section)| section)
+            .take(MAX_RESULTS)
+            .collect()
+    }
+}
+
+/// The lowercase words of `text`, without a plural `s`
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let word = word.to_lowercase();
+            match word.strip_suffix('s') {
+                Some(singular) if singular.len() > 2 && !singular.ends_with('s') => {
+                    singular.to_owned()
+                }
+                _ => word,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headings(index: &BookIndex, command: &str, query: &str) -> Vec<String> {
+        index
+            .search(find_book(command).unwrap(), query)
+            .iter()
+            .map(|section| section.heading.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let index = BookIndex::bundled();
+        assert!(index.len() > 1000);
+        let lifetimes = index.search(find_book("book").unwrap(), "lifetimes");
+        assert_eq!(lifetimes[0].heading, "Validating References with Lifetimes");
+        assert_eq!(
+            lifetimes[0].url(),
+            "https://doc.rust-lang.org/book/ch10-03-lifetime-syntax.html"
+        );
+        assert!(lifetimes.len() <= MAX_RESULTS);
+        assert_eq!(
+            headings(&index, "book", "lifetime elision")[0],
+            "Lifetime Elision"
+        );
+        assert!(headings(&index, "nomicon", "Send and Sync")
+            .iter()
+            .any(|heading| heading == "Send and Sync"));
+        assert!(headings(&index, "book", "").is_empty());
+        assert!(headings(&index, "reference", "nothing-like-this").is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        let index = BookIndex::parse(
+            "# header\nbook\tCh\tHeading\t3\tch.html#heading\nunknown\tCh\tH\t1\tch.html\nbook\tbroken\n",
+        );
+        assert_eq!(index.len(), 1);
+        assert_eq!(
+            index.sections[0].url(),
+            "https://doc.rust-lang.org/book/ch.html#heading"
+        );
+        assert!(!index.sections[0].is_chapter());
+    }
+
+    #[test]
+    fn test_search_async_book() {
+        let index = BookIndex::parse(concat!(
+            "async-book\tPinning\tPinning\t1\tpart-reference/pinning.html\n",
+            "async-book\tPinning\tPinning in practice\t2\tpart-reference/pinning.html#pinning-in-practice\n",
+            "async-book\tFutures\tThe Future trait\t2\tpart-guide/futures.html#the-future-trait\n",
+            "book\tFutures and the Async Syntax\tFutures and the Async Syntax\t1\tch17-01-futures-and-syntax.html\n",
+        ));
+        assert_eq!(index.len(), 4);
+        assert_eq!(
+            headings(&index, "async", "pinning"),
+            ["Pinning", "Pinning in practice"]
+        );
+        assert_eq!(
+            index.search(find_book("async").unwrap(), "future")[0].url(),
+            "https://rust-lang.github.io/async-book/part-guide/futures.html#the-future-trait"
+        );
+        assert_eq!(
+            headings(&index, "book", "future"),
+            ["Futures and the Async Syntax"]
+        );
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("Traits, and Lifetimes!"),
+            ["trait", "and", "lifetime"]
+        );
+        assert_eq!(words("process is_empty"), ["process", "is_empty"]);
+    }
+}
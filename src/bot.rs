@@ -1,23 +1,148 @@
+use crate::books::find_book;
+use crate::commands::{find_command, find_invocation, help_topic, HelpTopic, Invocation};
+use crate::config::{Config, CratesIoConfig, DefaultsConfig, PlaygroundConfig, PolicyDecision};
+use crate::crates_io::{cache_key, CrateInfo};
+use crate::dependencies::{find_external_crates, unsupported_crates, unsupported_crates_message};
 use crate::error::RustyBotError;
-use crate::playground::{PlaygroundAnswer, PlaygroundRequest};
-use crate::slack_conn::CodeReplyTemplate;
+use crate::metrics::{ExecutionOutcome, Metrics};
+use crate::playground::{
+    parse_gist_link, split_eval_result, CrateInformation, GistLink, PlaygroundAnswer,
+    PlaygroundRequest, VersionsResponse,
+};
+use crate::queue::{JobOwner, QueueFull};
+use crate::session::{normalize_entry, SessionKey};
+use crate::shutdown::PendingPlaceholder;
+use crate::slack_conn::{
+    truncate_output, BookReplyTemplate, CodeReplyTemplate, CrateReplyTemplate, CratesReplyTemplate,
+    DocReplyTemplate, HelpReplyTemplate, PlaceholderTemplate, VersionsReplyTemplate,
+    CANCEL_ACTION_ID,
+};
+use crate::state::BotState;
 use regex::Regex;
+use slack_morphism::errors::SlackClientError;
 use slack_morphism::prelude::*;
 use slack_morphism_hyper::*;
-use std::env;
+use std::future::Future;
 use std::sync::Arc;
-use tracing::{debug, error, instrument};
+use std::time::Duration;
+use tokio::join;
+use tokio::time::timeout;
+use tracing::{debug, error, info, instrument};
 use uuid::Uuid;
 
+/// Replaces the placeholders of the jobs still running when the bot stops
+pub const RESTARTING_MESSAGE: &str = "The bot is restarting, please retry in a minute.";
+
 pub async fn on_message(
     event: SlackPushEventCallback,
     client: Arc<SlackHyperClient>,
-    _states: Arc<SlackClientEventsUserState>,
+    states: Arc<SlackClientEventsUserState>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    tokio::spawn(async move { process_message(client, event).await });
+    let state = BotState::from_user_state(&states)?;
+    if !state.seen_events.first_time(&event_keys(&event)) {
+        debug!("Ignoring event {} delivered again", event.event_id);
+        return Ok(());
+    }
+    let in_flight = match state.shutdown.track() {
+        Some(in_flight) => in_flight,
+        None => {
+            debug!("Shutting down, ignoring event");
+            return Ok(());
+        }
+    };
+    tokio::spawn(async move {
+        let _in_flight = in_flight;
+        process_message(client, event, state).await
+    });
     Ok(())
 }
 
+pub async fn on_interaction(
+    event: SlackInteractionEvent,
+    client: Arc<SlackHyperClient>,
+    states: Arc<SlackClientEventsUserState>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let state = BotState::from_user_state(&states)?;
+    let in_flight = match state.shutdown.track() {
+        Some(in_flight) => in_flight,
+        None => {
+            debug!("Shutting down, ignoring interaction");
+            return Ok(());
+        }
+    };
+    if let SlackInteractionEvent::BlockActions(actions_event) = event {
+        tokio::spawn(async move {
+            let _in_flight = in_flight;
+            process_block_actions(client, actions_event, state).await
+        });
+    }
+    Ok(())
+}
+
+/// Runs the command given to the slash command (e.g. `/rusty versions`) like a message
+/// with the same text, the command is echoed in the channel
+pub async fn on_command(
+    event: SlackCommandEvent,
+    client: Arc<SlackHyperClient>,
+    states: Arc<SlackClientEventsUserState>,
+) -> Result<SlackCommandEventResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let state = BotState::from_user_state(&states)?;
+    let in_flight = match state.shutdown.track() {
+        Some(in_flight) => in_flight,
+        None => {
+            return Ok(SlackCommandEventResponse::new(
+                SlackMessageContent::new().with_text(RESTARTING_MESSAGE.to_owned()),
+            )
+            .with_response_type(SlackMessageResponseType::Ephemeral))
+        }
+    };
+    let text = slash_command_text(event.text.as_deref());
+    let echo = format!("{} {}", event.command, text.trim_start_matches('!'));
+    tokio::spawn(async move {
+        let _in_flight = in_flight;
+        let config = state.config.current();
+        let token = SlackApiToken::new(SlackApiTokenValue(config.slack.bot_token.clone()));
+        let session = client.open_session(&token);
+        // direct message channel ids start with a D
+        let is_dm = event.channel_id.0.starts_with('D');
+        process_command(
+            &session,
+            &state,
+            &config,
+            event.channel_id,
+            Some(event.user_id),
+            is_dm,
+            Some(text),
+        )
+        .await
+    });
+    Ok(
+        SlackCommandEventResponse::new(SlackMessageContent::new().with_text(echo))
+            .with_response_type(SlackMessageResponseType::InChannel),
+    )
+}
+
+/// Finds the bot's own user id, so the bot ignores its own messages
+pub async fn resolve_bot_user_id(
+    client: &SlackHyperClient,
+    config: &Config,
+) -> Option<SlackUserId> {
+    let token = SlackApiToken::new(SlackApiTokenValue(config.slack.bot_token.clone()));
+    match client.open_session(&token).auth_test().await {
+        Ok(response) => {
+            debug!("Running as user {}", response.user_id);
+            Some(response.user_id)
+        }
+        Err(e) => {
+            error!(
+                "Error: {}\n when resolving the bot user id with auth.test",
+                e
+            );
+            None
+        }
+    }
+}
+
 pub fn error_handler(
     err: Box<dyn std::error::Error + Send + Sync>,
     _client: Arc<SlackHyperClient>,
@@ -28,7 +153,7 @@ pub fn error_handler(
 }
 
 #[instrument(
-    skip(client, event),
+    skip(client, event, state),
     fields(
         request_id = %Uuid::new_v4()
         )
@@ -36,81 +161,40 @@ pub fn error_handler(
 async fn process_message(
     client: Arc<SlackHyperClient>,
     event: SlackPushEventCallback,
+    state: BotState,
 ) -> Result<(), RustyBotError> {
-    let token_value =
-        SlackApiTokenValue(env::var("SLACK_BOT_TOKEN").expect("SLACK_BOT_TOKEN env var not found"));
+    let config = state.config.current();
+    let token_value = SlackApiTokenValue(config.slack.bot_token.clone());
     let token = SlackApiToken::new(token_value);
     let session = client.open_session(&token);
-    let playground_url = env::var("PLAYGROUND_URL").expect("PLAYGROUND_URL env var not found");
 
     match event.event {
         SlackEventCallbackBody::Message(msg_event) => {
             debug!("Matched message");
+            if let Some(reason) = ignored_message(&msg_event, state.bot_user_id.as_ref()) {
+                debug!("Ignoring message {}: {}", msg_event.origin.ts, reason);
+                return Ok(());
+            }
+            let is_dm = matches!(msg_event.origin.channel_type, Some(ref channel_type) if channel_type.0 == "im");
             let channel = msg_event.origin.channel;
             let content = msg_event.content;
+            let user = msg_event.sender.user;
             if let Some(channel_id) = channel {
                 if let Some(msg_content) = content {
                     debug!(
                         "Found channel {} and content {:?}",
                         channel_id, msg_content.text
                     );
-                    let text = msg_content.text;
-                    debug!("Start matching has_ functions");
-                    // start matching the has_ functions
-                    // code
-                    if let Some(code) = has_code(&text) {
-                        debug!("Found code: {:?}", code);
-                        // print "executing"
-                        let reply_content =
-                            SlackMessageContent::new().with_text("Executing...".to_owned());
-                        let reply_request =
-                            SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
-                        let _response = session.chat_post_message(&reply_request).await;
-                        let response = eval_code(&code, &playground_url)
-                            .await
-                            .map_err(|e| {
-                                error!("Error: {}\n when executing eval_code with code type: {}\ncode text: {}\nbase URL {}", e, code.kind, code.text, playground_url);
-                                RustyBotError::InternalServerError(e.into())})?;
-                        let reply_content = CodeReplyTemplate::new(
-                            &response.link,
-                            response.playground_answer.stdout.clone(),
-                            response.playground_answer.stderr.clone(),
-                        );
-                        debug!(
-                            "Reply produced\nlink: {}\nstdout: {}\n stderr: {}",
-                            &response.link,
-                            response.playground_answer.stdout,
-                            response.playground_answer.stderr
-                        );
-                        let reply_request = SlackApiChatPostMessageRequest::new(
-                            channel_id,
-                            reply_content.render_template(),
-                        );
-                        let _response = session.chat_post_message(&reply_request).await;
-                        debug!(
-                            "Response from session.chat_post_message of code: {:?}",
-                            _response
-                        );
-                        return Ok(());
-                    }
-                    // command
-                    else if let Some(command) = has_command(&text) {
-                        debug!("Found command: {}", command);
-                        if let Some(output) = eval_command(command.clone()) {
-                            debug!("command {} produced output {}", command, output);
-                            let reply_content = SlackMessageContent::new().with_text(output);
-                            let reply_request =
-                                SlackApiChatPostMessageRequest::new(channel_id, reply_content);
-                            let _response = session.chat_post_message(&reply_request).await;
-                            debug!(
-                                "Response from session.chat_post_message of command: {:?}",
-                                _response
-                            );
-                        }
-                        return Ok(());
+                    // commands don't need the `!` in direct messages
+                    let text = if is_dm {
+                        msg_content.text.as_deref().and_then(implicit_command_text)
                     } else {
-                        return Ok(());
-                    }
+                        msg_content.text
+                    };
+                    return process_command(
+                        &session, &state, &config, channel_id, user, is_dm, text,
+                    )
+                    .await;
                 }
                 return Ok(());
             }
@@ -120,96 +204,1160 @@ async fn process_message(
             debug!("Matched mention");
             let channel_id = mention_event.channel;
             debug!("channel_id: {}", channel_id);
+            let is_dm = matches!(mention_event.origin.channel_type, Some(ref channel_type) if channel_type.0 == "im");
+            let text = mention_event.content.text.unwrap_or_default();
+            if find_invocation(&text).is_some() {
+                debug!("Mention with a `!` command, it is answered as a message");
+                return Ok(());
+            }
+            let text = implicit_command_text(&text);
+            if text.as_deref().and_then(find_invocation).is_some() {
+                return process_command(
+                    &session,
+                    &state,
+                    &config,
+                    channel_id,
+                    Some(mention_event.user),
+                    is_dm,
+                    text,
+                )
+                .await;
+            }
+            let decision = config.policy.check(&channel_id.0, is_dm, None);
+            if decision != PolicyDecision::Allow {
+                debug!(
+                    "Not answering mention in channel {}: {:?}",
+                    channel_id, decision
+                );
+                if let Some(output) = policy_reply(decision, "mention", &config) {
+                    let user = Some(mention_event.user);
+                    post_ephemeral(&session, &state, &channel_id, &user, output).await;
+                }
+                return Ok(());
+            }
             let reply_content =
                 SlackMessageContent::new().with_text("I'm alive, don't worry".to_owned());
             let reply_request = SlackApiChatPostMessageRequest::new(channel_id, reply_content);
-            let _response = session.chat_post_message(&reply_request).await;
-            debug!(
-                "Response from session.chat_post_message of mention: {:?}",
-                _response
-            );
+            let response = session.chat_post_message(&reply_request).await;
+            track_slack_response(&state.metrics, "chat_post_message", "mention", &response);
             Ok(())
         }
         _ => Ok(()),
     }
 }
 
-fn eval_command(command: String) -> Option<String> {
-    match command.to_lowercase().as_str() {
-        "docs" => Some("https://doc.rust-lang.org/".to_owned()),
-        "book" => Some("https://doc.rust-lang.org/book/".to_owned()),
-        _ => Some("*Available commands*\n!code - for complete code blocks\n!eval - for evaluating chunks that can fit in main function\n!help [docs, book] - links to classic rust material\n_Yours truely, Ferris_".to_owned()),
+/// Answers the command in `text`, sent by `user` in `channel_id` as a message or with the
+/// slash command
+async fn process_command(
+    session: &SlackClientSession<'_, SlackClientHyperHttpsConnector>,
+    state: &BotState,
+    config: &Config,
+    channel_id: SlackChannelId,
+    user: Option<SlackUserId>,
+    is_dm: bool,
+    text: Option<String>,
+) -> Result<(), RustyBotError> {
+    let invocation = match text.map(|text| resolve_alias(&text)) {
+        Some(text) => match find_invocation(&text) {
+            Some(invocation) => invocation,
+            None => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    let command = invocation.command.name;
+    let decision = config.policy.check(&channel_id.0, is_dm, Some(command));
+    if decision != PolicyDecision::Allow {
+        debug!(
+            "Not answering {} in channel {}: {:?}",
+            command, channel_id, decision
+        );
+        if let Some(output) = policy_reply(decision, command, config) {
+            post_ephemeral(session, state, &channel_id, &user, output).await;
+        }
+        return Ok(());
+    }
+    state.metrics.command(command);
+    debug!("Found {} command", command);
+    let request = CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        user,
+    };
+    (invocation.command.handler)(&request, &invocation).await
+}
+
+/// Where a command was sent and by whom, given to the command handlers
+pub struct CommandRequest<'a> {
+    session: &'a SlackClientSession<'a, SlackClientHyperHttpsConnector>,
+    state: &'a BotState,
+    config: &'a Config,
+    channel_id: SlackChannelId,
+    user: Option<SlackUserId>,
+}
+
+/// Runs the code of `!code`, `!eval` and `!let`
+pub(crate) async fn answer_code(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        user,
+    } = request;
+    let code = Code::new(invocation);
+    debug!("Found code: {:?}", code);
+    if !check_rate_limit(session, state, config, user, channel_id).await {
+        return Ok(());
+    }
+    let session_key = user
+        .as_ref()
+        .map(|user| SessionKey::new(&user.0, &channel_id.0));
+    let prelude = session_key
+        .as_ref()
+        .map(|key| state.sessions.prelude(key))
+        .unwrap_or_default();
+    if let Some(output) = check_dependencies(&code.text, state, &config.playground).await {
+        let reply_content = SlackMessageContent::new().with_text(output);
+        let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+        let response = session.chat_post_message(&reply_request).await;
+        track_slack_response(
+            &state.metrics,
+            "chat_post_message",
+            "unsupported crates",
+            &response,
+        );
+        return Ok(());
+    }
+    let execution = eval_code(&code, &prelude, state, config);
+    let (placeholder, response) =
+        match run_queued(session, state, config, channel_id, user, execution).await {
+            Some(queued) => queued,
+            None => return Ok(()),
+        };
+    state.metrics.execution(ExecutionOutcome::of(&response));
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            if !matches!(e, RustyBotError::ExecutionCancelled { .. }) {
+                error!("Error: {}\n when executing eval_code with code type: {}\ncode text: {}\nbase URLs {:?}", e, code.kind, code.text, config.playground.urls());
+            }
+            placeholder
+                .update(
+                    session,
+                    SlackMessageContent::new().with_text(e.user_message()),
+                )
+                .await;
+            return Ok(());
+        }
+    };
+    let share = code.share.unwrap_or(config.defaults.share);
+    let reply_content = code_reply(&response, config.limits.max_output_chars)
+        .with_share_failed(share && response.link.is_none());
+    debug!(
+        "Reply produced\nlink: {:?}\nstdout: {}\n stderr: {}\n result: {:?}",
+        &response.link,
+        response.playground_answer.stdout,
+        response.playground_answer.stderr,
+        response.result
+    );
+    placeholder
+        .update(session, reply_content.render_template())
+        .await;
+    if code.kind == *"let" {
+        let reply_text = match session_key {
+            Some(ref key) if response.playground_answer.success => {
+                let entries = state.sessions.push(key, normalize_entry(&code.text));
+                format!(
+                    "Added to your session ({} entries). Use `!session` to see it.",
+                    entries
+                )
+            }
+            Some(_) => "Not added to your session since it failed to run.".to_owned(),
+            None => "Sessions are only available for messages sent by users.".to_owned(),
+        };
+        let reply_request = SlackApiChatPostMessageRequest::new(
+            channel_id.clone(),
+            SlackMessageContent::new().with_text(reply_text),
+        );
+        let response = session.chat_post_message(&reply_request).await;
+        track_slack_response(
+            &state.metrics,
+            "chat_post_message",
+            "session note",
+            &response,
+        );
+    }
+    Ok(())
+}
+
+pub(crate) async fn answer_session(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        channel_id,
+        user,
+        ..
+    } = request;
+    let action = invocation.group("action").to_lowercase();
+    if let Some(ref user) = user {
+        let key = SessionKey::new(&user.0, &channel_id.0);
+        let output = eval_session_command(&action, &key, state);
+        let reply_content = SlackMessageContent::new().with_text(output);
+        let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+        let response = session.chat_post_message(&reply_request).await;
+        track_slack_response(
+            &state.metrics,
+            "chat_post_message",
+            "session command",
+            &response,
+        );
+    }
+    Ok(())
+}
+
+pub(crate) async fn answer_cancel(
+    request: &CommandRequest<'_>,
+    _invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        user,
+    } = request;
+    if let Some(ref user_id) = user {
+        let output = match state.queue.latest_job(&user_id.0, &channel_id.0) {
+            Some(job_id) => cancel_job(state, config, job_id, &user_id.0),
+            None => Some("You have no snippet waiting or running here.".to_owned()),
+        };
+        if let Some(output) = output {
+            post_ephemeral(session, state, channel_id, user, output).await;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn answer_gist(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        user,
+    } = request;
+    let gist = invocation.group("gist");
+    let reply_content = match parse_gist_link(gist) {
+        Some(gist_link) => {
+            if !check_rate_limit(session, state, config, user, channel_id).await {
+                return Ok(());
+            }
+            let execution = eval_gist(&gist_link, state, config);
+            let (placeholder, response) =
+                match run_queued(session, state, config, channel_id, user, execution).await {
+                    Some(queued) => queued,
+                    None => return Ok(()),
+                };
+            state.metrics.execution(ExecutionOutcome::of(&response));
+            let reply_content = match response {
+                Ok(response) => {
+                    code_reply(&response, config.limits.max_output_chars).render_template()
+                }
+                Err(
+                    e @ (RustyBotError::InvalidPlaygroundSetting { .. }
+                    | RustyBotError::ExecutionTimeout { .. }
+                    | RustyBotError::ExecutionCancelled { .. }
+                    | RustyBotError::PlaygroundTimeout
+                    | RustyBotError::PlaygroundRateLimited { .. }
+                    | RustyBotError::PlaygroundError(_)),
+                ) => SlackMessageContent::new().with_text(e.user_message()),
+                Err(e) => {
+                    error!("Error: {}\n when running gist {}", e, gist_link.id);
+                    SlackMessageContent::new().with_text(format!(
+                        "Couldn't run the gist `{}`, make sure it exists and try again.",
+                        gist_link.id
+                    ))
+                }
+            };
+            placeholder.update(session, reply_content).await;
+            return Ok(());
+        }
+        None => SlackMessageContent::new()
+            .with_text(format!("`{}` is not a playground link or a gist id", gist)),
+    };
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", "gist", &response);
+    Ok(())
+}
+
+pub(crate) async fn answer_reload_config(
+    request: &CommandRequest<'_>,
+    _invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        user,
+    } = request;
+    let output = match user {
+        Some(ref user) if config.is_admin(&user.0) => match state.reload_config() {
+            Ok(_) => "Configuration reloaded.".to_owned(),
+            Err(e) => format!(
+                "The configuration was not reloaded, the current one is kept.\n```{}```",
+                e
+            ),
+        },
+        _ => "Only admins can reload the configuration.".to_owned(),
+    };
+    let reply_content = SlackMessageContent::new().with_text(output);
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(
+        &state.metrics,
+        "chat_post_message",
+        "reload-config",
+        &response,
+    );
+    Ok(())
+}
+
+pub(crate) async fn answer_crates(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        ..
+    } = request;
+    let filter = invocation.group("filter");
+    let reply_content = match playground_crates(state, &config.playground).await {
+        Ok(crates) => {
+            let filter = if filter.is_empty() {
+                None
+            } else {
+                Some(filter.to_owned())
+            };
+            CratesReplyTemplate::new(crates, filter).render_template()
+        }
+        Err(e) => {
+            error!("Error: {}\n when fetching the crates list", e);
+            SlackMessageContent::new().with_text(
+                "Couldn't get the crates list from the playground, please try again later."
+                    .to_owned(),
+            )
+        }
+    };
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", "crates", &response);
+    Ok(())
+}
+
+pub(crate) async fn answer_crate(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        ..
+    } = request;
+    let name = invocation.group("name");
+    let reply_content = if name.is_empty() {
+        HelpReplyTemplate::new(HelpTopic::Command(invocation.command)).render_template()
+    } else {
+        match crates_io_crate(state, &config.crates_io, name).await {
+            Ok(krate) => {
+                CrateReplyTemplate::new(&krate, config.crates_io.base_url()).render_template()
+            }
+            Err(
+                e @ (RustyBotError::CrateNotFound { .. } | RustyBotError::CratesIoUnavailable(_)),
+            ) => SlackMessageContent::new().with_text(e.user_message()),
+            Err(e) => {
+                error!("Error: {}\n when looking up the crate {}", e, name);
+                SlackMessageContent::new().with_text(format!(
+                    "Couldn't look up the crate `{}`, please try again later.",
+                    name
+                ))
+            }
+        }
+    };
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", "crate", &response);
+    Ok(())
+}
+
+pub(crate) async fn answer_versions(
+    request: &CommandRequest<'_>,
+    _invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        config,
+        channel_id,
+        ..
+    } = request;
+    let reply_content = match playground_versions(state, &config.playground).await {
+        Ok(versions) => VersionsReplyTemplate::new(versions).render_template(),
+        Err(e) => {
+            error!("Error: {}\n when fetching the toolchain versions", e);
+            SlackMessageContent::new().with_text(
+                "Couldn't get the toolchain versions from the playground, please try again later."
+                    .to_owned(),
+            )
+        }
+    };
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", "versions", &response);
+    Ok(())
+}
+
+pub(crate) async fn answer_doc(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        channel_id,
+        ..
+    } = request;
+    let path = invocation.group("path");
+    let reply_content = if path.is_empty() {
+        HelpReplyTemplate::new(HelpTopic::Command(invocation.command)).render_template()
+    } else {
+        DocReplyTemplate::new(path, state.docs.lookup(path)).render_template()
+    };
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", "doc", &response);
+    Ok(())
+}
+
+/// Searches the book named like the command, e.g. `!nomicon send and sync`
+pub(crate) async fn answer_book(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        channel_id,
+        ..
+    } = request;
+    let book = match find_book(invocation.command.name) {
+        Some(book) => book,
+        None => {
+            error!("Error: no book for the {} command", invocation.command.name);
+            return Ok(());
+        }
+    };
+    let query = invocation.group("query").trim();
+    let reply_content = if query.is_empty() {
+        HelpReplyTemplate::new(HelpTopic::Command(invocation.command)).render_template()
+    } else {
+        BookReplyTemplate::new(book, query, state.books.search(book, query)).render_template()
+    };
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", book.command, &response);
+    Ok(())
+}
+
+pub(crate) async fn answer_help(
+    request: &CommandRequest<'_>,
+    invocation: &Invocation,
+) -> Result<(), RustyBotError> {
+    let CommandRequest {
+        session,
+        state,
+        channel_id,
+        ..
+    } = request;
+    let reply_content =
+        HelpReplyTemplate::new(help_topic(invocation.group("topic"))).render_template();
+    let reply_request = SlackApiChatPostMessageRequest::new(channel_id.clone(), reply_content);
+    let response = session.chat_post_message(&reply_request).await;
+    track_slack_response(&state.metrics, "chat_post_message", "help", &response);
+    Ok(())
+}
+
+/// The reply to a run, with the output and the result cut down to `max_output_chars` so
+/// they fit in Slack's sections
+fn code_reply(answer: &PlaygroundAnswer, max_output_chars: usize) -> CodeReplyTemplate<'_> {
+    CodeReplyTemplate::new(
+        answer.link.as_deref(),
+        truncate_output(&answer.playground_answer.stdout, max_output_chars),
+        truncate_output(&answer.playground_answer.stderr, max_output_chars),
+    )
+    .with_result(
+        answer
+            .result
+            .as_deref()
+            .map(|result| truncate_output(result, max_output_chars)),
+    )
+    .with_cached(answer.cached)
+}
+
+/// The message shown while a job waits in the queue and runs, replaced by its reply
+struct Placeholder {
+    channel: SlackChannelId,
+    ts: Option<SlackTs>,
+    metrics: Arc<Metrics>,
+    /// Lets the shutdown tell the user to retry if the job doesn't finish in time
+    _pending: Option<PendingPlaceholder>,
+}
+
+impl Placeholder {
+    async fn post(
+        session: &SlackClientSession<'_, SlackClientHyperHttpsConnector>,
+        state: &BotState,
+        channel: &SlackChannelId,
+        content: SlackMessageContent,
+    ) -> Self {
+        let reply_request = SlackApiChatPostMessageRequest::new(channel.clone(), content);
+        let response = session.chat_post_message(&reply_request).await;
+        track_slack_response(
+            &state.metrics,
+            "chat_post_message",
+            "placeholder",
+            &response,
+        );
+        let ts = response.ok().map(|response| response.ts);
+        Self {
+            channel: channel.clone(),
+            _pending: ts
+                .clone()
+                .map(|ts| state.shutdown.add_placeholder(channel.clone(), ts)),
+            ts,
+            metrics: state.metrics.clone(),
+        }
+    }
+
+    /// Replaces the placeholder with `content`, which is posted as a new message when
+    /// the placeholder couldn't be posted
+    async fn update(
+        &self,
+        session: &SlackClientSession<'_, SlackClientHyperHttpsConnector>,
+        content: SlackMessageContent,
+    ) {
+        match self.ts {
+            Some(ref ts) => {
+                let update_request =
+                    SlackApiChatUpdateRequest::new(self.channel.clone(), content, ts.clone());
+                let response = session.chat_update(&update_request).await;
+                track_slack_response(&self.metrics, "chat_update", "placeholder", &response);
+            }
+            None => {
+                let reply_request =
+                    SlackApiChatPostMessageRequest::new(self.channel.clone(), content);
+                let response = session.chat_post_message(&reply_request).await;
+                track_slack_response(&self.metrics, "chat_post_message", "reply", &response);
+            }
+        }
+    }
+}
+
+/// Stops handling events and waits up to `limits.shutdown_timeout_secs` for the ones in
+/// flight, then tells the users whose jobs are still running to retry
+pub async fn shut_down(client: Arc<SlackHyperClient>, state: &BotState) {
+    let config = state.config.current();
+    let deadline = Duration::from_secs(config.limits.shutdown_timeout_secs);
+    state.metrics.set_slack_connected(false);
+    info!(
+        "Waiting up to {:?} for {} events in flight",
+        deadline,
+        state.shutdown.in_flight()
+    );
+    if state.shutdown.drain(deadline).await {
+        return;
+    }
+    let token = SlackApiToken::new(SlackApiTokenValue(config.slack.bot_token.clone()));
+    let session = client.open_session(&token);
+    for (channel, ts) in state.shutdown.pending_placeholders() {
+        let update_request = SlackApiChatUpdateRequest::new(
+            channel,
+            SlackMessageContent::new().with_text(RESTARTING_MESSAGE.to_owned()),
+            ts,
+        );
+        let response = session.chat_update(&update_request).await;
+        track_slack_response(&state.metrics, "chat_update", "restart notice", &response);
+    }
+}
+
+/// Runs `execution` once a worker of the execution queue is free, showing the position in
+/// the queue and then "Executing..." in a placeholder message with a Cancel button.
+/// Returns `None` when the queue is full, the user is asked to retry later.
+async fn run_queued<T, F>(
+    session: &SlackClientSession<'_, SlackClientHyperHttpsConnector>,
+    state: &BotState,
+    config: &Config,
+    channel_id: &SlackChannelId,
+    user: &Option<SlackUserId>,
+    execution: F,
+) -> Option<(Placeholder, Result<T, RustyBotError>)>
+where
+    F: Future<Output = Result<T, RustyBotError>>,
+{
+    let owner = JobOwner {
+        user: user.as_ref().map(|user| user.0.clone()),
+        channel: channel_id.0.clone(),
+    };
+    let mut ticket = match state.queue.enqueue(config.queue.max_depth, owner) {
+        Ok(ticket) => ticket,
+        Err(QueueFull) => {
+            debug!(
+                "Execution queue is full, rejecting job in channel {}",
+                channel_id
+            );
+            post_ephemeral(
+                session,
+                state,
+                channel_id,
+                user,
+                "Too many snippets are waiting to run, please retry in a minute.".to_owned(),
+            )
+            .await;
+            return None;
+        }
+    };
+    let job_id = ticket.id();
+    let cancellation = ticket.cancellation();
+    let text = match ticket.position() {
+        0 => "Executing...".to_owned(),
+        position => format!("Queued, position {} in line...", position),
+    };
+    let placeholder = Placeholder::post(
+        session,
+        state,
+        channel_id,
+        PlaceholderTemplate::new(text, job_id).render_template(),
+    )
+    .await;
+    let secs = config.queue.job_timeout_secs;
+    let run = async {
+        ticket.wait_turn().await;
+        if ticket.position() > 0 {
+            let content = PlaceholderTemplate::new("Executing...".to_owned(), job_id);
+            placeholder.update(session, content.render_template()).await;
+        }
+        match timeout(Duration::from_secs(secs), execution).await {
+            Ok(result) => result,
+            Err(_) => Err(RustyBotError::ExecutionTimeout { secs }),
+        }
+    };
+    // dropping the execution when the job is cancelled aborts the playground request
+    let result = tokio::select! {
+        result = run => result,
+        user = cancellation.wait() => Err(RustyBotError::ExecutionCancelled { user }),
+    };
+    Some((placeholder, result))
+}
+
+/// Replies only to `user`, when the message was sent by one
+async fn post_ephemeral(
+    session: &SlackClientSession<'_, SlackClientHyperHttpsConnector>,
+    state: &BotState,
+    channel_id: &SlackChannelId,
+    user: &Option<SlackUserId>,
+    text: String,
+) {
+    if let Some(user) = user {
+        let reply_request = SlackApiChatPostEphemeralRequest::new(
+            channel_id.clone(),
+            user.clone(),
+            SlackMessageContent::new().with_text(text),
+        );
+        let response = session.chat_post_ephemeral(&reply_request).await;
+        track_slack_response(&state.metrics, "chat_post_ephemeral", "reply", &response);
+    }
+}
+
+/// Logs the response of a Slack API call and counts it in the metrics when it failed
+fn track_slack_response<T: std::fmt::Debug>(
+    metrics: &Metrics,
+    method: &str,
+    what: &str,
+    response: &Result<T, SlackClientError>,
+) {
+    debug!(
+        "Response from session.{} of {}: {:?}",
+        method, what, response
+    );
+    if response.is_err() {
+        metrics.slack_api_error(method);
+    }
+}
+
+/// Takes a run from the rate limits before executing code. When a limit is hit the user
+/// is told when to retry and `false` is returned.
+async fn check_rate_limit(
+    session: &SlackClientSession<'_, SlackClientHyperHttpsConnector>,
+    state: &BotState,
+    config: &Config,
+    user: &Option<SlackUserId>,
+    channel_id: &SlackChannelId,
+) -> bool {
+    let user_id = user.as_ref().map(|user| user.0.as_str());
+    match state
+        .rate_limiter
+        .check(&config.rate_limits, user_id, &channel_id.0)
+    {
+        Ok(()) => true,
+        Err(limited) => {
+            debug!(
+                "Rate limited in channel {} by user {:?}: {:?}",
+                channel_id, user_id, limited
+            );
+            post_ephemeral(session, state, channel_id, user, limited.message()).await;
+            false
+        }
+    }
+}
+
+/// The reply to a command the policy doesn't allow, `None` when it is silently ignored
+fn policy_reply(decision: PolicyDecision, command: &str, config: &Config) -> Option<String> {
+    match decision {
+        PolicyDecision::Allow | PolicyDecision::Ignore => None,
+        PolicyDecision::DmOnly => Some(config.policy.dm_only_message.clone()),
+        PolicyDecision::CommandDisabled => Some(format!("`!{}` is not enabled here.", command)),
+    }
+}
+
+async fn process_block_actions(
+    client: Arc<SlackHyperClient>,
+    event: SlackInteractionBlockActionsEvent,
+    state: BotState,
+) -> Result<(), RustyBotError> {
+    let config = state.config.current();
+    let token = SlackApiToken::new(SlackApiTokenValue(config.slack.bot_token.clone()));
+    let session = client.open_session(&token);
+    let (user, channel_id) = match (event.user, event.channel) {
+        (Some(user), Some(channel)) => (user.id, channel.id),
+        _ => return Ok(()),
+    };
+    for action in event.actions.unwrap_or_default() {
+        if action.action_id.0 != CANCEL_ACTION_ID {
+            continue;
+        }
+        debug!("Found cancel action: {:?}", action.value);
+        let job_id = action.value.and_then(|value| value.parse().ok());
+        let output = match job_id {
+            Some(job_id) => cancel_job(&state, &config, job_id, &user.0),
+            None => None,
+        };
+        if let Some(output) = output {
+            post_ephemeral(&session, &state, &channel_id, &Some(user.clone()), output).await;
+        }
+    }
+    Ok(())
+}
+
+/// Cancels a job for `user`, who must have queued it or be an admin. Returns a reply
+/// when the job can't be cancelled, its placeholder is updated otherwise.
+fn cancel_job(state: &BotState, config: &Config, job_id: u64, user: &str) -> Option<String> {
+    match state.queue.owner(job_id) {
+        None => Some("This snippet is not running anymore.".to_owned()),
+        Some(JobOwner {
+            user: Some(ref owner),
+            ..
+        }) if owner != user && !config.is_admin(user) => {
+            Some(format!("Only <@{}> can cancel this snippet.", owner))
+        }
+        Some(_) if state.queue.cancel(job_id, user) => None,
+        Some(_) => Some("This snippet is not running anymore.".to_owned()),
+    }
+}
+
+fn eval_session_command(action: &str, key: &SessionKey, state: &BotState) -> String {
+    match action {
+        "" | "show" => {
+            let entries = state.sessions.entries(key);
+            if entries.is_empty() {
+                "Your session is empty. Add statements or items to it with `!let`.".to_owned()
+            } else {
+                format!("*Your session*\n```{}```", entries.join("\n"))
+            }
+        }
+        "undo" => match state.sessions.undo(key) {
+            Some(entry) => format!("Removed from your session:\n```{}```", entry),
+            None => "Your session is empty, nothing to undo.".to_owned(),
+        },
+        "export" => format!("```{}```", state.sessions.export(key)),
+        "reset" => {
+            state.sessions.reset(key);
+            "Your session was cleared.".to_owned()
+        }
+        _ => "*Session commands*\n!let - run a code block and keep it in your session\n!session [show, undo, export, reset] - manage your session".to_owned(),
+    }
+}
+
+async fn playground_crates(
+    state: &BotState,
+    config: &PlaygroundConfig,
+) -> Result<Vec<CrateInformation>, RustyBotError> {
+    state
+        .crates
+        .get_or_try_fetch(|| {
+            state.endpoints.call(config, |url| async move {
+                state
+                    .playground
+                    .fetch_crates(&url)
+                    .await
+                    .map(|response| response.crates)
+            })
+        })
+        .await
+}
+
+/// The crate named `name`, from the cache when it was looked up recently
+async fn crates_io_crate(
+    state: &BotState,
+    config: &CratesIoConfig,
+    name: &str,
+) -> Result<CrateInfo, RustyBotError> {
+    let limits = config.cache_limits();
+    let key = cache_key(name);
+    if let Some(krate) = state.crate_info.get(&limits, key) {
+        debug!("Found cached crate {}", name);
+        return Ok(krate);
+    }
+    let krate = state.crates_io.fetch_crate(config.base_url(), name).await?;
+    state.crate_info.insert(&limits, key, krate.clone());
+    Ok(krate)
+}
+
+async fn playground_versions(
+    state: &BotState,
+    config: &PlaygroundConfig,
+) -> Result<VersionsResponse, RustyBotError> {
+    state
+        .versions
+        .get_or_try_fetch(|| {
+            state.endpoints.call(config, |url| async move {
+                state.playground.fetch_versions(&url).await
+            })
+        })
+        .await
+}
+
+/// Returns a reply listing the available crates when the code uses one that isn't allowed.
+/// If the crates list can't be fetched the code is let through, the playground will complain.
+async fn check_dependencies(
+    code: &str,
+    state: &BotState,
+    config: &PlaygroundConfig,
+) -> Option<String> {
+    let used = find_external_crates(code);
+    if used.is_empty() {
+        return None;
+    }
+    let available = match state.config.current().crates_allowlist {
+        Some(ref allowlist) => allowlist.clone(),
+        None => {
+            let crates = playground_crates(state, config).await;
+            match crates {
+                Ok(crates) => crates.into_iter().map(|krate| krate.id).collect(),
+                Err(e) => {
+                    error!(
+                        "Error: {}\n when fetching the crates list, skipping dependency check",
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+    };
+    let unsupported = unsupported_crates(&used, &available);
+    if unsupported.is_empty() {
+        None
+    } else {
+        debug!("Unsupported crates: {:?}", unsupported);
+        Some(unsupported_crates_message(&unsupported, &available))
     }
 }
 
-async fn eval_code(code: &Code, playground_url: &str) -> Result<PlaygroundAnswer, RustyBotError> {
+/// Runs the code, or takes its result from the result cache when the same code ran
+/// recently with the same settings. The playground endpoints are tried in order.
+async fn eval_code(
+    code: &Code,
+    prelude: &str,
+    state: &BotState,
+    config: &Config,
+) -> Result<PlaygroundAnswer, RustyBotError> {
     let request;
     if code.kind == *"code" {
         request = PlaygroundRequest::new(code.text.clone()).escape_html();
     } else if code.kind == *"eval" {
-        request = PlaygroundRequest::new_eval(code.text.clone()).escape_html();
+        request = PlaygroundRequest::new_eval(format!("{}{}", prelude, code.text)).escape_html();
+    } else if code.kind == *"let" {
+        request =
+            PlaygroundRequest::new_eval(format!("{}{}", prelude, normalize_entry(&code.text)))
+                .escape_html();
     } else {
         error!("Error: InvalidBotCommand reached! code kind: {}", code.kind);
         return Err(RustyBotError::InvalidBotCommand {
-            // never really reached because of has_code matching
+            // never really reached, only `answer_code` calls it
             command: code.kind.to_owned(),
         });
     };
-    let result = request.execute(playground_url).await;
+    let request = with_defaults(request, &config.defaults)?;
+    let share = code.share.unwrap_or(config.defaults.share);
+    let cache_key = request.cache_key();
+    match state.results.get(&config.result_cache, cache_key) {
+        // a private run doesn't have the link this one needs
+        Some(cached) if share && cached.link.is_none() => {}
+        Some(cached) => {
+            debug!("Found cached result for key {}", cache_key);
+            return Ok(PlaygroundAnswer {
+                link: if share { cached.link } else { None },
+                cached: true,
+                ..cached
+            });
+        }
+        None => {}
+    }
+    let request = &request;
+    let execution = state.endpoints.call(&config.playground, |url| async move {
+        state.playground.execute(&url, request).await
+    });
+    let (result, link) = if share {
+        let share_link = state.endpoints.call(&config.playground, |url| async move {
+            state.playground.create_share_link(&url, request).await
+        });
+        let (result, link) = join!(execution, share_link);
+        let link = link
+            .map_err(|e| {
+                error!(
+                    "Error: {}\n when creating the share link, replying without it",
+                    e
+                )
+            })
+            .ok();
+        (result, link)
+    } else {
+        (execution.await, None)
+    };
     match result {
-        Ok(res) => {
+        Ok(mut res) => {
+            let mut eval_result = None;
+            if code.kind == *"eval" {
+                let (stdout, value) = split_eval_result(&res.playground_response.stdout);
+                res.playground_response.stdout = stdout;
+                eval_result = value;
+            }
             let ans = PlaygroundAnswer {
                 playground_answer: res.playground_response,
-                link: request.create_share_link(playground_url).await?,
+                result: eval_result,
+                link,
+                cached: false,
             };
+            state
+                .results
+                .insert(&config.result_cache, cache_key, ans.clone());
             Ok(ans)
         }
         Err(e) => {
             error!(
-                "Error: {}\nerror during eval_code when trying to run execute function on request to base URL: {}",
-                e, playground_url
+                "Error: {}\nerror during eval_code when trying to run execute function on request to base URLs: {:?}",
+                e, config.playground.urls()
             );
-            Err(RustyBotError::InternalServerError(e.into()))
+            Err(e)
         }
     }
 }
 
-#[derive(Debug)]
-struct Code {
-    kind: String,
-    text: String,
+fn with_defaults(
+    request: PlaygroundRequest,
+    defaults: &DefaultsConfig,
+) -> Result<PlaygroundRequest, RustyBotError> {
+    request
+        .with_channel(&defaults.channel)?
+        .with_mode(&defaults.mode)?
+        .with_edition(&defaults.edition)
 }
 
-fn has_code(message: &Option<String>) -> Option<Code> {
-    match *message {
-        Some(ref text) => {
-            let re = Regex::new(r"!(?P<kind>code|eval)\n```?(?s:(?P<code>.*?))```")
-                .expect("code regex should not fail");
-            let code_result = re.captures(text).map(|capture| Code {
-                kind: String::from(&capture["kind"]),
-                text: String::from(&capture["code"]),
-            });
-            code_result
-        }
-        _ => None,
+/// Runs the code of an existing gist with the settings from its link
+async fn eval_gist(
+    gist: &GistLink,
+    state: &BotState,
+    config: &Config,
+) -> Result<PlaygroundAnswer, RustyBotError> {
+    let gist_response = state
+        .endpoints
+        .call(&config.playground, |url| async move {
+            state.playground.fetch_gist(&url, &gist.id).await
+        })
+        .await?;
+    let mut request = with_defaults(PlaygroundRequest::new(gist_response.code), &config.defaults)?;
+    if let Some(ref channel) = gist.channel {
+        request = request.with_channel(channel)?;
+    }
+    if let Some(ref mode) = gist.mode {
+        request = request.with_mode(mode)?;
+    }
+    if let Some(ref edition) = gist.edition {
+        request = request.with_edition(edition)?;
     }
+    let request = &request;
+    let response = state
+        .endpoints
+        .call(&config.playground, |url| async move {
+            state.playground.execute(&url, request).await
+        })
+        .await
+        .map_err(|e| {
+            error!(
+                "Error: {}\nerror during eval_gist when trying to run execute function on request to base URLs: {:?}",
+                e, config.playground.urls()
+            );
+            e
+        })?;
+    Ok(PlaygroundAnswer {
+        playground_answer: response.playground_response,
+        result: None,
+        link: Some(request.share_link(&gist.id)),
+        cached: false,
+    })
 }
 
-fn has_command(message: &Option<String>) -> Option<String> {
-    match *message {
-        Some(ref text) => {
-            let re =
-                Regex::new(r"!help\s(?P<command>.*?)$").expect("command regex should not fail");
-            let command_result = re
-                .captures(text)
-                .map(|capture| String::from(&capture["command"]));
-            if command_result == Some("".to_owned()) {
-                None
-            } else {
-                command_result
+/// Keys identifying the event for deduplication: Slack keeps the event id when it retries a
+/// delivery, and the message timestamp identifies a message sent again in a new event
+fn event_keys(event: &SlackPushEventCallback) -> Vec<String> {
+    let mut keys = vec![format!("event:{}", event.event_id)];
+    match &event.event {
+        SlackEventCallbackBody::Message(msg_event) => {
+            if let Some(channel) = &msg_event.origin.channel {
+                keys.push(format!("message:{}:{}", channel, msg_event.origin.ts));
             }
         }
-        _ => None,
+        SlackEventCallbackBody::AppMention(mention_event) => {
+            keys.push(format!(
+                "mention:{}:{}",
+                mention_event.channel, mention_event.origin.ts
+            ));
+        }
+        _ => {}
+    }
+    keys
+}
+
+/// Why the message isn't handled: it is sent by a bot (this one included), or it is not a
+/// plain message but e.g. an edit, a deletion or a channel join
+fn ignored_message(
+    msg_event: &SlackMessageEvent,
+    bot_user_id: Option<&SlackUserId>,
+) -> Option<&'static str> {
+    if msg_event.sender.bot_id.is_some() {
+        Some("sent by a bot")
+    } else if bot_user_id.is_some() && msg_event.sender.user.as_ref() == bot_user_id {
+        Some("sent by the bot itself")
+    } else if msg_event.subtype.is_some() {
+        Some("not a plain message")
+    } else {
+        None
+    }
+}
+
+/// The message equivalent to the text of the slash command, `!help` when it is empty
+fn slash_command_text(text: Option<&str>) -> String {
+    match text.map(str::trim) {
+        None | Some("") => "!help".to_owned(),
+        Some(text) if text.starts_with('!') => text.to_owned(),
+        Some(text) => format!("!{}", text),
+    }
+}
+
+/// The command in a message sent without the `!` prefix, i.e. a mention of the bot or a
+/// direct message: `help` becomes `!help` and a bare code block is run with `!code` when it
+/// has a `main` function, with `!eval` otherwise. Mentions of users are left out.
+fn implicit_command_text(text: &str) -> Option<String> {
+    let text = Regex::new(r"<@[A-Z0-9]+(\|[^>]*)?>")
+        .expect("mention regex should not fail")
+        .replace_all(text, "");
+    let text = text.trim();
+    if text.is_empty() {
+        return Some("!help".to_owned());
+    }
+    if find_invocation(text).is_some() {
+        return Some(text.to_owned());
+    }
+    if text.starts_with("```") {
+        let has_main = Regex::new(r"\bfn\s+main\s*\(")
+            .expect("main regex should not fail")
+            .is_match(text);
+        let kind = if has_main { "code" } else { "eval" };
+        return Some(format!("!{}\n{}", kind, text));
+    }
+    let word_end = text.find(char::is_whitespace).unwrap_or(text.len());
+    let (first_word, rest) = text.split_at(word_end);
+    find_command(first_word).map(|command| format!("!{}{}", command.name, rest))
+}
+
+/// Replaces the alias of the command starting `text` with its name, e.g. `!stop` with
+/// `!cancel`. The rest of the text is left as is, it may be code using `!stop`.
+fn resolve_alias(text: &str) -> String {
+    let re =
+        Regex::new(r"^(?P<space>\s*)!(?P<name>[\w-]+)\b").expect("alias regex should not fail");
+    match re.captures(text) {
+        Some(capture) => match find_command(&capture["name"]) {
+            Some(command) if command.aliases.contains(&&capture["name"]) => format!(
+                "{}!{}{}",
+                &capture["space"],
+                command.name,
+                &text[capture[0].len()..]
+            ),
+            _ => text.to_owned(),
+        },
+        None => text.to_owned(),
+    }
+}
+
+#[derive(Debug)]
+struct Code {
+    kind: String,
+    text: String,
+    /// Whether to create a share link, set with `--share` or `--no-share` after the command
+    share: Option<bool>,
+}
+
+impl Code {
+    fn new(invocation: &Invocation) -> Self {
+        Code {
+            kind: invocation.command.name.to_owned(),
+            text: invocation.group("code").to_owned(),
+            share: invocation
+                .group("flags")
+                .split_whitespace()
+                .rev()
+                .find_map(|flag| match flag {
+                    "--share" => Some(true),
+                    "--no-share" => Some(false),
+                    _ => None,
+                }),
+        }
     }
 }
 
@@ -218,41 +1366,124 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_has_command() {
-        let message_with_command = &Some("!help book".to_owned());
-        let message_without_command = &Some("nothing here".to_owned());
-        assert_eq!(has_command(message_with_command), Some("book".to_owned()));
-        assert!(has_command(message_without_command).is_none());
+    fn test_code() {
+        let code = Code::new(&find_invocation("!eval\n```this is code```").unwrap());
+        assert_eq!(code.kind, "eval".to_owned());
+        assert_eq!(code.text, "this is code".to_owned());
+        assert!(code.share.is_none());
+
+        let private_code =
+            Code::new(&find_invocation("!code --no-share\n```fn main() {}```").unwrap());
+        assert_eq!(private_code.kind, "code".to_owned());
+        assert_eq!(private_code.share, Some(false));
     }
 
     #[test]
-    fn test_has_code() {
-        let message_with_code = &Some("!eval\n```this is code```".to_owned());
-        let message_without_code = &Some("!bla\n```this is not code```".to_owned());
+    fn test_code_reply() {
+        use crate::playground::PlaygroundResponse;
 
-        let ans_with_code = has_code(message_with_code).unwrap();
-        assert_eq!(ans_with_code.kind, "eval".to_owned());
-        assert_eq!(ans_with_code.text, "this is code".to_owned());
-
-        let ans_without_code = has_code(message_without_code);
-        assert!(ans_without_code.is_none());
+        let answer = PlaygroundAnswer {
+            playground_answer: PlaygroundResponse {
+                success: true,
+                stdout: "out".repeat(10),
+                stderr: String::new(),
+            },
+            result: Some("[\n    1,\n]".repeat(1000)),
+            link: None,
+            cached: true,
+        };
+        let reply = code_reply(&answer, 20);
+        assert_eq!(reply.stdout, truncate_output(&"out".repeat(10), 20));
+        let result = reply.result.unwrap();
+        assert!(result.ends_with("\n... (truncated)"));
+        assert_eq!(result.chars().count(), 20 + "\n... (truncated)".len());
+        assert!(reply.cached);
     }
 
     #[test]
-    fn test_eval_command() {
-        let command_docs = "docs".to_owned();
-        let command_book = "book".to_owned();
+    fn test_implicit_command_text() {
+        assert_eq!(implicit_command_text("<@U123>"), Some("!help".to_owned()));
+        assert_eq!(
+            implicit_command_text("<@U123> help book"),
+            Some("!help book".to_owned())
+        );
+        assert_eq!(
+            implicit_command_text("Versions"),
+            Some("!versions".to_owned())
+        );
+        assert_eq!(
+            implicit_command_text("<@U123> ```let x = 1;```"),
+            Some("!eval\n```let x = 1;```".to_owned())
+        );
+        assert_eq!(
+            implicit_command_text("```fn main() {}```"),
+            Some("!code\n```fn main() {}```".to_owned())
+        );
+        assert_eq!(
+            implicit_command_text("eval --no-share\n```1 + 1```"),
+            Some("!eval --no-share\n```1 + 1```".to_owned())
+        );
+        assert_eq!(
+            implicit_command_text("please !versions"),
+            Some("please !versions".to_owned())
+        );
+        assert_eq!(implicit_command_text("how are you?"), None);
+    }
 
-        let expected_reply_docs = "https://doc.rust-lang.org/".to_owned();
-        let expected_reply_book = "https://doc.rust-lang.org/book/".to_owned();
-        let expected_reply_other = "*Available commands*\n!code - for complete code blocks\n!eval - for evaluating chunks that can fit in main function\n!help [docs, book] - links to classic rust material\n_Yours truely, Ferris_".to_owned();
+    #[test]
+    fn test_slash_command_text() {
+        assert_eq!(slash_command_text(Some(" versions ")), "!versions");
+        assert_eq!(slash_command_text(Some("!crates rand")), "!crates rand");
+        assert_eq!(slash_command_text(Some("")), "!help");
+        assert_eq!(slash_command_text(None), "!help");
+    }
 
-        let reply_docs = eval_command(command_docs).unwrap();
-        let reply_book = eval_command(command_book).unwrap();
-        let reply_other = eval_command("something".to_owned()).unwrap();
+    #[test]
+    fn test_ignored_message() {
+        let message = |extra: &str| -> SlackMessageEvent {
+            serde_json::from_str(&format!(
+                r#"{{"ts": "1.0", "channel": "C1", "text": "!versions"{}}}"#,
+                extra
+            ))
+            .unwrap()
+        };
+        let bot_user_id = SlackUserId("UBOT".to_owned());
+        assert_eq!(
+            ignored_message(&message(r#", "user": "U1""#), Some(&bot_user_id)),
+            None
+        );
+        assert!(ignored_message(&message(r#", "bot_id": "B1""#), Some(&bot_user_id)).is_some());
+        assert!(ignored_message(&message(r#", "user": "UBOT""#), Some(&bot_user_id)).is_some());
+        assert!(ignored_message(
+            &message(r#", "user": "U1", "subtype": "message_changed""#),
+            Some(&bot_user_id)
+        )
+        .is_some());
+        assert_eq!(ignored_message(&message(r#", "user": "U1""#), None), None);
+    }
 
-        assert_eq!(expected_reply_docs, reply_docs);
-        assert_eq!(expected_reply_book, reply_book);
-        assert_eq!(expected_reply_other, reply_other);
+    #[test]
+    fn test_resolve_alias() {
+        assert_eq!(resolve_alias("!stop"), "!cancel");
+        assert_eq!(resolve_alias(" !version please"), " !versions please");
+        assert_eq!(resolve_alias("hey !version please"), "hey !version please");
+        assert_eq!(resolve_alias("!versions"), "!versions");
+        assert_eq!(
+            resolve_alias("!eval\n```println!(\"{:?}\", vec![1]);```"),
+            "!eval\n```println!(\"{:?}\", vec![1]);```"
+        );
+        assert_eq!(
+            resolve_alias("!eval\n```let stop = false;\nwhile !stop { break }```"),
+            "!eval\n```let stop = false;\nwhile !stop { break }```"
+        );
+        assert_eq!(
+            resolve_alias("!code\n```if !commands.is_empty() {}```"),
+            "!code\n```if !commands.is_empty() {}```"
+        );
+        assert_eq!(resolve_alias("!Stop"), "!Stop");
+        assert_eq!(
+            implicit_command_text("stop").map(|text| resolve_alias(&text)),
+            Some("!cancel".to_owned())
+        );
     }
 }
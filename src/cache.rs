@@ -0,0 +1,125 @@
+use crate::config::ResultCacheConfig;
+use crate::error::RustyBotError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// A single value that is fetched lazily and refetched once it's older than its TTL
+#[derive(Debug)]
+pub struct TtlCell<T> {
+    ttl: RwLock<Duration>,
+    value: Mutex<Option<(Instant, T)>>,
+}
+
+impl<T: Clone> TtlCell<T> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: RwLock::new(ttl),
+            value: Mutex::new(None),
+        }
+    }
+
+    pub fn set_ttl(&self, ttl: Duration) {
+        *self.ttl.write().expect("ttl lock poisoned") = ttl;
+    }
+
+    /// Returns the cached value, or fetches a fresh one if it is missing or expired.
+    /// Concurrent callers wait for a single fetch instead of all hitting the source.
+    pub async fn get_or_try_fetch<F, Fut>(&self, fetch: F) -> Result<T, RustyBotError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, RustyBotError>>,
+    {
+        let ttl = *self.ttl.read().expect("ttl lock poisoned");
+        let mut value = self.value.lock().await;
+        if let Some((fetched_at, ref cached)) = *value {
+            if fetched_at.elapsed() < ttl {
+                return Ok(cached.clone());
+            }
+        }
+        let fresh = fetch().await?;
+        *value = Some((Instant::now(), fresh.clone()));
+        Ok(fresh)
+    }
+}
+
+/// Values kept for `ttl_secs` each, at most `max_entries` of them with the oldest dropped
+/// first. The limits are passed on every call so a reloaded configuration applies right away.
+#[derive(Debug)]
+pub struct ResultCache<T> {
+    entries: std::sync::Mutex<HashMap<u64, (Instant, T)>>,
+}
+
+impl<T: Clone> Default for ResultCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> ResultCache<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, limits: &ResultCacheConfig, key: u64) -> Option<T> {
+        if !limits.is_enabled() {
+            return None;
+        }
+        let ttl = Duration::from_secs(limits.ttl_secs);
+        let entries = self.entries.lock().expect("result cache lock poisoned");
+        entries
+            .get(&key)
+            .filter(|(stored_at, _)| stored_at.elapsed() < ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, limits: &ResultCacheConfig, key: u64, value: T) {
+        if !limits.is_enabled() {
+            return;
+        }
+        let ttl = Duration::from_secs(limits.ttl_secs);
+        let mut entries = self.entries.lock().expect("result cache lock poisoned");
+        entries.retain(|_, (stored_at, _)| stored_at.elapsed() < ttl);
+        while entries.len() >= limits.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (stored_at, _))| *stored_at)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.insert(key, (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_cache() {
+        let cache = ResultCache::new();
+        let limits = ResultCacheConfig {
+            ttl_secs: 60,
+            max_entries: 2,
+        };
+        cache.insert(&limits, 1, "one");
+        cache.insert(&limits, 2, "two");
+        assert_eq!(cache.get(&limits, 1), Some("one"));
+        cache.insert(&limits, 3, "three");
+        assert_eq!(cache.get(&limits, 1), None);
+        assert_eq!(cache.get(&limits, 3), Some("three"));
+
+        let disabled = ResultCacheConfig {
+            ttl_secs: 60,
+            max_entries: 0,
+        };
+        assert_eq!(cache.get(&disabled, 3), None);
+    }
+}
@@ -0,0 +1,460 @@
+use crate::bot::{
+    answer_book, answer_cancel, answer_code, answer_crate, answer_crates, answer_doc, answer_gist,
+    answer_help, answer_reload_config, answer_session, answer_versions, CommandRequest,
+};
+use crate::error::RustyBotError;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Answers an invocation of a command
+pub type Handler =
+    for<'a> fn(
+        &'a CommandRequest<'a>,
+        &'a Invocation,
+    ) -> Pin<Box<dyn Future<Output = Result<(), RustyBotError>> + Send + 'a>>;
+
+/// A command of the bot, as listed by `!help`
+#[derive(Clone, Copy)]
+pub struct CommandSpec {
+    /// Also the name used in the `[policy]` configuration
+    pub name: &'static str,
+    /// Other names the command answers to
+    pub aliases: &'static [&'static str],
+    pub arguments: &'static str,
+    pub description: &'static str,
+    pub examples: &'static [&'static str],
+    /// Finds the command in a message, its named groups are the arguments of the handler
+    pub pattern: &'static str,
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    /// How to call the command, e.g. `!crates [name]`
+    pub fn usage(&self) -> String {
+        if self.arguments.is_empty() {
+            format!("!{}", self.name)
+        } else {
+            format!("!{} {}", self.name, self.arguments)
+        }
+    }
+
+    /// The first call of the command in `text`, with the groups captured by its pattern
+    pub fn invocation(&'static self, text: &str) -> Option<Invocation> {
+        let re = Regex::new(self.pattern).expect("command regex should not fail");
+        re.captures(text).map(|capture| Invocation {
+            command: self,
+            groups: re
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    capture
+                        .name(name)
+                        .map(|group| (name.to_owned(), group.as_str().to_owned()))
+                })
+                .collect(),
+        })
+    }
+}
+
+// the handler can't be compared or printed
+impl PartialEq for CommandSpec {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for CommandSpec {}
+
+impl fmt::Debug for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandSpec")
+            .field("name", &self.name)
+            .field("pattern", &self.pattern)
+            .finish()
+    }
+}
+
+/// A command found in a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub command: &'static CommandSpec,
+    groups: HashMap<String, String>,
+}
+
+impl Invocation {
+    /// The text captured by the group `name` of the pattern, empty when it didn't match
+    pub fn group(&self, name: &str) -> &str {
+        self.groups
+            .get(name)
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+/// Every command of the bot, in the order `!help` lists them and they are looked for in
+/// a message
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "code",
+        aliases: &[],
+        arguments: "[--no-share]",
+        description: "run a complete program, given in a code block on the next line",
+        examples: &["!code\n```fn main() {\n    println!(\"Hello, world!\");\n}```"],
+        pattern: r"!code(?P<flags>[^\n]*)\n```?(?s:(?P<code>.*?))```",
+        handler: |request, invocation| Box::pin(answer_code(request, invocation)),
+    },
+    CommandSpec {
+        name: "eval",
+        aliases: &[],
+        arguments: "[--no-share]",
+        description: "evaluate code that fits in the main function, a final expression without a semicolon is printed",
+        examples: &["!eval\n```let v = vec![1, 2, 3];\nv.iter().sum::<i32>()```"],
+        pattern: r"!eval(?P<flags>[^\n]*)\n```?(?s:(?P<code>.*?))```",
+        handler: |request, invocation| Box::pin(answer_code(request, invocation)),
+    },
+    CommandSpec {
+        name: "let",
+        aliases: &[],
+        arguments: "[--no-share]",
+        description: "like !eval, and keeps the code in your session for the next runs",
+        examples: &["!let\n```let answer = 42;```", "!eval\n```answer * 2```"],
+        pattern: r"!let(?P<flags>[^\n]*)\n```?(?s:(?P<code>.*?))```",
+        handler: |request, invocation| Box::pin(answer_code(request, invocation)),
+    },
+    CommandSpec {
+        name: "session",
+        aliases: &[],
+        arguments: "[show, undo, export, reset]",
+        description: "manage your session",
+        examples: &["!session", "!session undo"],
+        pattern: r"!session\b[ \t]*(?P<action>\w*)",
+        handler: |request, invocation| Box::pin(answer_session(request, invocation)),
+    },
+    CommandSpec {
+        name: "cancel",
+        aliases: &["stop"],
+        arguments: "",
+        description: "cancel your last snippet waiting or running",
+        examples: &["!cancel"],
+        pattern: r"!cancel\b",
+        handler: |request, invocation| Box::pin(answer_cancel(request, invocation)),
+    },
+    CommandSpec {
+        name: "gist",
+        aliases: &[],
+        arguments: "<link or id>",
+        description: "run the code of a playground share link",
+        examples: &["!gist https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist=<id>"],
+        pattern: r"!gist\s+(?P<gist>\S+)",
+        handler: |request, invocation| Box::pin(answer_gist(request, invocation)),
+    },
+    CommandSpec {
+        name: "crates",
+        aliases: &[],
+        arguments: "[name]",
+        description: "list the crates available on the playground",
+        examples: &["!crates", "!crates rand"],
+        pattern: r"!crates\b[ \t]*(?P<filter>[\w-]*)",
+        handler: |request, invocation| Box::pin(answer_crates(request, invocation)),
+    },
+    CommandSpec {
+        name: "crate",
+        aliases: &[],
+        arguments: "<name>",
+        description: "show a crate from crates.io, with its latest version and a line for your Cargo.toml",
+        examples: &["!crate serde"],
+        pattern: r"!crate\b[ \t]*(?P<name>[\w-]*)",
+        handler: |request, invocation| Box::pin(answer_crate(request, invocation)),
+    },
+    CommandSpec {
+        name: "versions",
+        aliases: &["version"],
+        arguments: "",
+        description: "show the playground toolchain versions",
+        examples: &["!versions"],
+        pattern: r"!versions\b",
+        handler: |request, invocation| Box::pin(answer_versions(request, invocation)),
+    },
+    CommandSpec {
+        name: "doc",
+        aliases: &["docs"],
+        arguments: "<path>",
+        description: "show the documentation of a std, core or alloc item",
+        examples: &["!doc Vec::retain", "!doc Iterator::fold"],
+        pattern: r"!doc\b[ \t]*(?P<path>\S*)",
+        handler: |request, invocation| Box::pin(answer_doc(request, invocation)),
+    },
+    CommandSpec {
+        name: "book",
+        aliases: &[],
+        arguments: "<words>",
+        description: "search the chapters and headings of The Rust Programming Language",
+        examples: &["!book lifetimes", "!book trait objects"],
+        pattern: r"!book\b[ \t]*(?P<query>[^\n]*)",
+        handler: |request, invocation| Box::pin(answer_book(request, invocation)),
+    },
+    CommandSpec {
+        name: "reference",
+        aliases: &[],
+        arguments: "<words>",
+        description: "search the chapters and headings of The Rust Reference",
+        examples: &["!reference lifetime elision"],
+        pattern: r"!reference\b[ \t]*(?P<query>[^\n]*)",
+        handler: |request, invocation| Box::pin(answer_book(request, invocation)),
+    },
+    CommandSpec {
+        name: "nomicon",
+        aliases: &[],
+        arguments: "<words>",
+        description: "search the chapters and headings of The Rustonomicon",
+        examples: &["!nomicon send and sync"],
+        pattern: r"!nomicon\b[ \t]*(?P<query>[^\n]*)",
+        handler: |request, invocation| Box::pin(answer_book(request, invocation)),
+    },
+    CommandSpec {
+        name: "rbe",
+        aliases: &[],
+        arguments: "<words>",
+        description: "search the chapters and headings of Rust by Example",
+        examples: &["!rbe closures"],
+        pattern: r"!rbe\b[ \t]*(?P<query>[^\n]*)",
+        handler: |request, invocation| Box::pin(answer_book(request, invocation)),
+    },
+    CommandSpec {
+        name: "async",
+        aliases: &[],
+        arguments: "<words>",
+        description: "search the chapters and headings of Asynchronous Programming in Rust",
+        examples: &["!async pinning"],
+        pattern: r"!async\b[ \t]*(?P<query>[^\n]*)",
+        handler: |request, invocation| Box::pin(answer_book(request, invocation)),
+    },
+    CommandSpec {
+        name: "reload-config",
+        aliases: &[],
+        arguments: "",
+        description: "reload the configuration, for admins only",
+        examples: &["!reload-config"],
+        pattern: r"!reload-config\b",
+        handler: |request, invocation| Box::pin(answer_reload_config(request, invocation)),
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["commands"],
+        arguments: "[command, docs, book]",
+        description: "list the commands, show how to use one of them or link to classic rust material",
+        examples: &["!help", "!help eval", "!help book"],
+        pattern: r"!help\b[ \t]*(?P<topic>.*?)$",
+        handler: |request, invocation| Box::pin(answer_help(request, invocation)),
+    },
+];
+
+/// Topics of `!help` that link to rust material
+pub const HELP_LINKS: &[(&str, &str)] = &[
+    ("docs", "https://doc.rust-lang.org/"),
+    ("book", "https://doc.rust-lang.org/book/"),
+];
+
+/// What `!help <topic>` answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelpTopic {
+    Commands,
+    Command(&'static CommandSpec),
+    Link(&'static str),
+}
+
+/// The command named or aliased `name`, with or without the `!`
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    let name = name.trim_start_matches('!').to_lowercase();
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name || command.aliases.contains(&name.as_str()))
+}
+
+/// The first command of `COMMANDS` called in `text`
+pub fn find_invocation(text: &str) -> Option<Invocation> {
+    COMMANDS.iter().find_map(|command| command.invocation(text))
+}
+
+pub fn command_names() -> Vec<&'static str> {
+    COMMANDS.iter().map(|command| command.name).collect()
+}
+
+/// The commands with their `!`, e.g. `!code, !eval`
+pub fn command_list() -> String {
+    COMMANDS
+        .iter()
+        .map(|command| format!("!{}", command.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn help_topic(topic: &str) -> HelpTopic {
+    let topic = topic.trim().to_lowercase();
+    if let Some((_, link)) = HELP_LINKS.iter().find(|(name, _)| *name == topic) {
+        HelpTopic::Link(link)
+    } else {
+        match find_command(&topic) {
+            Some(command) => HelpTopic::Command(command),
+            None => HelpTopic::Commands,
+        }
+    }
+}
+
+/// The list of commands as plain text
+pub fn commands_text() -> String {
+    let lines: Vec<String> = COMMANDS
+        .iter()
+        .map(|command| format!("{} - {}", command.usage(), command.description))
+        .collect();
+    format!(
+        "*Available commands*\n{}\n_Yours truly, Ferris_",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_command() {
+        assert_eq!(find_command("crates").map(|c| c.name), Some("crates"));
+        assert_eq!(find_command("!Version").map(|c| c.name), Some("versions"));
+        assert_eq!(find_command("stop").map(|c| c.name), Some("cancel"));
+        assert_eq!(find_command("docs").map(|c| c.name), Some("doc"));
+        assert!(find_command("nothing").is_none());
+    }
+
+    /// The command called in `text` and what the group `name` of its pattern captured
+    fn invocation(text: &str, name: &str) -> Option<(&'static str, String)> {
+        find_invocation(text)
+            .map(|invocation| (invocation.command.name, invocation.group(name).to_owned()))
+    }
+
+    #[test]
+    fn test_find_invocation() {
+        assert_eq!(
+            invocation("!let\n```let x = 5;```", "code"),
+            Some(("let", "let x = 5;".to_owned()))
+        );
+        assert_eq!(
+            invocation("!eval\n```this is code```", "code"),
+            Some(("eval", "this is code".to_owned()))
+        );
+        assert_eq!(
+            invocation("!help book", "topic"),
+            Some(("help", "book".to_owned()))
+        );
+        assert_eq!(invocation("!help", "topic"), Some(("help", "".to_owned())));
+        assert!(find_invocation("nothing here").is_none());
+        assert!(find_invocation("!bla\n```this is not code```").is_none());
+    }
+
+    #[test]
+    fn test_find_invocation_arguments() {
+        assert_eq!(
+            invocation("!session", "action"),
+            Some(("session", "".to_owned()))
+        );
+        assert_eq!(
+            invocation("!session undo", "action"),
+            Some(("session", "undo".to_owned()))
+        );
+        assert_eq!(
+            invocation("!crates", "filter"),
+            Some(("crates", "".to_owned()))
+        );
+        assert_eq!(
+            invocation("!crates serde", "filter"),
+            Some(("crates", "serde".to_owned()))
+        );
+        assert_eq!(
+            invocation("!crate tokio-util, please", "name"),
+            Some(("crate", "tokio-util".to_owned()))
+        );
+        assert_eq!(invocation("!crate", "name"), Some(("crate", "".to_owned())));
+        assert_eq!(
+            invocation(
+                "!gist <https://play.rust-lang.org/?version=stable&amp;gist=101>",
+                "gist"
+            ),
+            Some((
+                "gist",
+                "<https://play.rust-lang.org/?version=stable&amp;gist=101>".to_owned()
+            ))
+        );
+        assert_eq!(
+            invocation("!doc `Iterator::fold` please", "path"),
+            Some(("doc", "`Iterator::fold`".to_owned()))
+        );
+        assert_eq!(invocation("!doc", "path"), Some(("doc", "".to_owned())));
+        assert_eq!(
+            invocation("!nomicon send and sync\nthanks", "query"),
+            Some(("nomicon", "send and sync".to_owned()))
+        );
+        assert_eq!(invocation("!rbe", "query"), Some(("rbe", "".to_owned())));
+        assert_eq!(
+            invocation("!async pinning", "query"),
+            Some(("async", "pinning".to_owned()))
+        );
+        assert_eq!(
+            invocation("!versions", ""),
+            Some(("versions", "".to_owned()))
+        );
+        assert_eq!(invocation("!cancel", ""), Some(("cancel", "".to_owned())));
+    }
+
+    #[test]
+    fn test_find_invocation_whole_names() {
+        assert_eq!(
+            invocation("!help crates", ""),
+            Some(("help", "".to_owned()))
+        );
+        assert_eq!(
+            invocation("!crates rand", ""),
+            Some(("crates", "".to_owned()))
+        );
+        assert_eq!(invocation("!help book", ""), Some(("help", "".to_owned())));
+        for text in [
+            "!sessions",
+            "!version",
+            "!cancelled",
+            "!gist",
+            "!docs",
+            "!books",
+        ] {
+            assert!(find_invocation(text).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_help_topic() {
+        assert_eq!(
+            help_topic("docs"),
+            HelpTopic::Link("https://doc.rust-lang.org/")
+        );
+        assert_eq!(
+            help_topic(" Book "),
+            HelpTopic::Link("https://doc.rust-lang.org/book/")
+        );
+        assert_eq!(
+            help_topic("!eval"),
+            HelpTopic::Command(find_command("eval").unwrap())
+        );
+        assert_eq!(help_topic(""), HelpTopic::Commands);
+        assert_eq!(help_topic("something"), HelpTopic::Commands);
+    }
+
+    #[test]
+    fn test_commands_text() {
+        let text = commands_text();
+        assert!(text.starts_with("*Available commands*\n!code [--no-share] - run a complete"));
+        assert!(text.contains("\n!crates [name] - list the crates available on the playground\n"));
+        assert!(text.ends_with("_Yours truly, Ferris_"));
+        assert_eq!(text.lines().count(), COMMANDS.len() + 2);
+    }
+}
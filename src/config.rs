@@ -0,0 +1,863 @@
+use crate::commands::command_names;
+use crate::error::RustyBotError;
+use crate::playground::{find_setting, CHANNELS, EDITIONS, MODES};
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+/// Read when the `RUSTY_CONFIG` env var doesn't point to another file. It is optional,
+/// everything can be set through env vars as well.
+pub const DEFAULT_CONFIG_PATH: &str = "rusty.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub log_level: String,
+    pub slack: SlackConfig,
+    pub http: HttpConfig,
+    pub monitoring: MonitoringConfig,
+    pub playground: PlaygroundConfig,
+    pub crates_io: CratesIoConfig,
+    pub defaults: DefaultsConfig,
+    pub limits: LimitsConfig,
+    pub rate_limits: RateLimitsConfig,
+    pub queue: QueueConfig,
+    pub result_cache: ResultCacheConfig,
+    /// Third party crates snippets may use, the playground's crates when not set
+    pub crates_allowlist: Option<Vec<String>>,
+    pub policy: PolicyConfig,
+    /// Users (ids) allowed to run admin commands like `!reload-config`
+    pub admin_users: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlackConfig {
+    pub bot_token: String,
+    /// Only used in Socket Mode
+    pub app_token: String,
+    /// Verifies the requests Slack sends in HTTP mode
+    pub signing_secret: String,
+    /// How the bot receives events, read at startup only
+    pub mode: SlackMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlackMode {
+    /// Events come through a websocket opened by the bot
+    Socket,
+    /// Slack sends events to the bot's HTTP server (Events API), see `HttpConfig`
+    Http,
+}
+
+/// The HTTP server receiving Slack requests in HTTP mode, read at startup only
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub address: String,
+    pub events_path: String,
+    pub interactions_path: String,
+    pub commands_path: String,
+    /// Requests signed longer ago are rejected to prevent replays
+    pub max_request_age_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaygroundConfig {
+    pub url: String,
+    /// Endpoints tried in order when `url` fails
+    pub fallback_urls: Vec<String>,
+    pub connect_timeout_secs: u64,
+    /// Covers the whole request, including the time the playground takes to run the code
+    pub request_timeout_secs: u64,
+    /// Retries of GET requests failing with a network error or a 5xx status, POSTs are
+    /// only retried when the connection failed
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for every following one
+    pub retry_backoff_ms: u64,
+    pub user_agent: String,
+    /// Failures in a row after which an endpoint is skipped for `circuit_open_secs`
+    pub failure_threshold: u32,
+    pub circuit_open_secs: u64,
+    /// How often the endpoints are checked, 0 disables the checks
+    pub health_check_interval_secs: u64,
+}
+
+/// The crates.io API used by `!crate`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CratesIoConfig {
+    /// Any server with the crates.io API, e.g. a mirror
+    pub url: String,
+    /// crates.io asks for a user agent with a way to contact the bot's owner, read at
+    /// startup only
+    pub user_agent: String,
+    /// Read at startup only
+    pub request_timeout_secs: u64,
+    /// How long crates are cached, 0 disables the cache
+    pub cache_ttl_secs: u64,
+    pub cache_max_entries: usize,
+}
+
+/// The server exposing `/healthz`, `/readyz` and `/metrics`, read at startup only
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitoringConfig {
+    pub enabled: bool,
+    pub address: String,
+}
+
+/// Settings used to run snippets that don't specify their own
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultsConfig {
+    pub channel: String,
+    pub edition: String,
+    pub mode: String,
+    /// Whether snippets get a share link, changed per snippet with `--share`/`--no-share`
+    pub share: bool,
+}
+
+/// Where the bot answers and which commands it accepts there
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Channels the bot answers in, all of them when empty
+    pub allowed_channels: Vec<String>,
+    /// Channels the bot never answers in, even when they are allowed
+    pub denied_channels: Vec<String>,
+    /// Only answer in direct messages, commands sent in channels get `dm_only_message`
+    pub dm_only: bool,
+    pub dm_only_message: String,
+    /// Commands enabled in direct messages and in channels without rules of their own,
+    /// all of them when not set
+    pub commands: Option<Vec<String>>,
+    /// Rules of specific channels, by channel id
+    pub channels: HashMap<String, ChannelPolicy>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelPolicy {
+    /// Commands enabled in the channel
+    pub commands: Vec<String>,
+}
+
+/// What to do with a message according to the `PolicyConfig`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyDecision {
+    Allow,
+    /// The channel is not allowed, the message is silently ignored
+    Ignore,
+    /// The bot only answers in direct messages
+    DmOnly,
+    /// The command is not enabled where it was sent
+    CommandDisabled,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Sessions that weren't used for this long are dropped
+    pub session_ttl_secs: u64,
+    /// How long the playground's crates list and toolchain versions are cached
+    pub metadata_ttl_secs: u64,
+    /// Longer stdout/stderr are truncated in replies, Slack rejects sections over 3000 chars
+    pub max_output_chars: usize,
+    /// How long the jobs still running get to finish when the bot stops
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    /// Snippets run at the same time, only read at startup
+    pub workers: usize,
+    /// Snippets that can wait for a worker, more are rejected
+    pub max_depth: usize,
+    /// Runs taking longer are stopped
+    pub job_timeout_secs: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            max_depth: 20,
+            job_timeout_secs: 60,
+        }
+    }
+}
+
+/// Results of snippets reused when the same code runs again with the same settings,
+/// setting either to 0 disables the cache
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResultCacheConfig {
+    pub ttl_secs: u64,
+    pub max_entries: usize,
+}
+
+impl ResultCacheConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.ttl_secs > 0 && self.max_entries > 0
+    }
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 10 * 60,
+            max_entries: 500,
+        }
+    }
+}
+
+/// How many snippets can be run, see `RateLimiter`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitsConfig {
+    pub user: RateLimitConfig,
+    pub channel: RateLimitConfig,
+    pub global: RateLimitConfig,
+}
+
+/// A token bucket holding up to `burst` runs and refilled with `per_minute` runs per
+/// minute. Setting either to 0 disables the limit.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl RateLimitConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.burst > 0 && self.per_minute > 0
+    }
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        Self {
+            user: RateLimitConfig {
+                burst: 3,
+                per_minute: 6,
+            },
+            channel: RateLimitConfig {
+                burst: 10,
+                per_minute: 30,
+            },
+            global: RateLimitConfig {
+                burst: 20,
+                per_minute: 60,
+            },
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_owned(),
+            slack: SlackConfig::default(),
+            http: HttpConfig::default(),
+            monitoring: MonitoringConfig::default(),
+            playground: PlaygroundConfig::default(),
+            crates_io: CratesIoConfig::default(),
+            defaults: DefaultsConfig::default(),
+            limits: LimitsConfig::default(),
+            rate_limits: RateLimitsConfig::default(),
+            queue: QueueConfig::default(),
+            result_cache: ResultCacheConfig::default(),
+            crates_allowlist: None,
+            policy: PolicyConfig::default(),
+            admin_users: Vec::new(),
+        }
+    }
+}
+
+impl Default for SlackConfig {
+    fn default() -> Self {
+        Self {
+            bot_token: String::new(),
+            app_token: String::new(),
+            signing_secret: String::new(),
+            mode: SlackMode::Socket,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0:8080".to_owned(),
+            events_path: "/push".to_owned(),
+            interactions_path: "/interaction".to_owned(),
+            commands_path: "/command".to_owned(),
+            max_request_age_secs: 300,
+        }
+    }
+}
+
+impl Default for MonitoringConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            address: "127.0.0.1:9100".to_owned(),
+        }
+    }
+}
+
+impl Default for PlaygroundConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://play.rust-lang.org".to_owned(),
+            fallback_urls: Vec::new(),
+            connect_timeout_secs: 5,
+            request_timeout_secs: 60,
+            max_retries: 2,
+            retry_backoff_ms: 500,
+            user_agent: concat!("rusty-slackbot/", env!("CARGO_PKG_VERSION")).to_owned(),
+            failure_threshold: 3,
+            circuit_open_secs: 30,
+            health_check_interval_secs: 60,
+        }
+    }
+}
+
+impl Default for CratesIoConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://crates.io".to_owned(),
+            user_agent: concat!(
+                "rusty-slackbot/",
+                env!("CARGO_PKG_VERSION"),
+                " (https://github.com/oren0e/rusty-slackbot)"
+            )
+            .to_owned(),
+            request_timeout_secs: 10,
+            cache_ttl_secs: 60 * 60,
+            cache_max_entries: 200,
+        }
+    }
+}
+
+impl CratesIoConfig {
+    /// The base URL without a trailing slash
+    pub fn base_url(&self) -> &str {
+        self.url.trim_end_matches('/')
+    }
+
+    /// The limits of the crates cache, in the same shape as the result cache
+    pub fn cache_limits(&self) -> ResultCacheConfig {
+        ResultCacheConfig {
+            ttl_secs: self.cache_ttl_secs,
+            max_entries: self.cache_max_entries,
+        }
+    }
+}
+
+impl Default for DefaultsConfig {
+    fn default() -> Self {
+        Self {
+            channel: "stable".to_owned(),
+            edition: "2021".to_owned(),
+            mode: "debug".to_owned(),
+            share: true,
+        }
+    }
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            allowed_channels: Vec::new(),
+            denied_channels: Vec::new(),
+            dm_only: false,
+            dm_only_message: "I only answer in direct messages, send me your command there :crab:"
+                .to_owned(),
+            commands: None,
+            channels: HashMap::new(),
+        }
+    }
+}
+
+impl PlaygroundConfig {
+    /// The endpoint base URLs in the order they are tried, without trailing slashes
+    pub fn urls(&self) -> Vec<String> {
+        std::iter::once(&self.url)
+            .chain(&self.fallback_urls)
+            .map(|url| url.trim_end_matches('/').to_owned())
+            .collect()
+    }
+}
+
+impl PolicyConfig {
+    /// Decides whether `command` (a name from `COMMANDS`) is answered in `channel`.
+    /// Without a command only the channel rules are checked. The channel lists don't
+    /// apply to direct messages.
+    pub fn check(&self, channel: &str, is_dm: bool, command: Option<&str>) -> PolicyDecision {
+        if !is_dm {
+            let allowed = self.allowed_channels.is_empty()
+                || self.allowed_channels.iter().any(|c| c == channel);
+            if !allowed || self.denied_channels.iter().any(|c| c == channel) {
+                return PolicyDecision::Ignore;
+            }
+            if self.dm_only {
+                return PolicyDecision::DmOnly;
+            }
+        }
+        let enabled = match (self.channels.get(channel), &self.commands) {
+            (Some(rules), _) if !is_dm => Some(&rules.commands),
+            (_, Some(commands)) => Some(commands),
+            _ => None,
+        };
+        match (command, enabled) {
+            (Some(command), Some(enabled)) if !enabled.iter().any(|c| c == command) => {
+                PolicyDecision::CommandDisabled
+            }
+            _ => PolicyDecision::Allow,
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            session_ttl_secs: 30 * 60,
+            metadata_ttl_secs: 60 * 60,
+            max_output_chars: 2900,
+            shutdown_timeout_secs: 20,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file (see `DEFAULT_CONFIG_PATH`), applies the env var overrides
+    /// and validates the result
+    pub fn load() -> Result<Self, RustyBotError> {
+        let mut config = match env::var("RUSTY_CONFIG") {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => Self::default(),
+        };
+        config.apply_env_overrides(|key| env::var(key).ok());
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, RustyBotError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            RustyBotError::InvalidConfig(format!(
+                "could not read config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_toml(&content).map_err(|e| match e {
+            RustyBotError::InvalidConfig(message) => {
+                RustyBotError::InvalidConfig(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, RustyBotError> {
+        toml::from_str(content).map_err(|e| RustyBotError::InvalidConfig(e.to_string()))
+    }
+
+    /// Overrides settings with the env vars the bot was configured with before it had a
+    /// config file, `var` looks an env var up
+    pub fn apply_env_overrides<F: Fn(&str) -> Option<String>>(&mut self, var: F) {
+        let list = |value: String| -> Vec<String> {
+            value
+                .split(',')
+                .map(|item| item.trim().to_owned())
+                .filter(|item| !item.is_empty())
+                .collect()
+        };
+        if let Some(value) = var("RUSTY_LOG_LEVEL") {
+            self.log_level = value;
+        }
+        if let Some(value) = var("SLACK_BOT_TOKEN") {
+            self.slack.bot_token = value;
+        }
+        if let Some(value) = var("SLACK_SIGNING_SECRET") {
+            self.slack.signing_secret = value;
+        }
+        match var("RUSTY_MODE").as_deref() {
+            Some("socket") => self.slack.mode = SlackMode::Socket,
+            Some("http") => self.slack.mode = SlackMode::Http,
+            _ => {}
+        }
+        if let Some(value) = var("RUSTY_HTTP_ADDRESS") {
+            self.http.address = value;
+        }
+        if let Some(value) = var("RUSTY_MONITORING_ADDRESS") {
+            self.monitoring.address = value;
+        }
+        if let Some(value) = var("SLACK_APP_TOKEN") {
+            self.slack.app_token = value;
+        }
+        if let Some(value) = var("PLAYGROUND_URL") {
+            self.playground.url = value;
+        }
+        if let Some(value) = var("PLAYGROUND_FALLBACK_URLS") {
+            self.playground.fallback_urls = list(value);
+        }
+        if let Some(value) = var("CRATES_IO_URL") {
+            self.crates_io.url = value;
+        }
+        if let Some(value) = var("CRATES_ALLOWLIST") {
+            self.crates_allowlist = Some(list(value));
+        }
+        if let Some(value) = var("RUSTY_ALLOWED_CHANNELS") {
+            self.policy.allowed_channels = list(value);
+        }
+        if let Some(value) = var("RUSTY_DENIED_CHANNELS") {
+            self.policy.denied_channels = list(value);
+        }
+        if let Some(value) = var("RUSTY_DM_ONLY") {
+            self.policy.dm_only = value == "true" || value == "1";
+        }
+        if let Some(value) = var("RUSTY_ADMIN_USERS") {
+            self.admin_users = list(value);
+        }
+        if let Some(value) = var("PLAYGROUND_METADATA_TTL_SECS").and_then(|v| v.parse().ok()) {
+            self.limits.metadata_ttl_secs = value;
+        }
+    }
+
+    /// Checks every setting and reports all the problems found at once
+    pub fn validate(&self) -> Result<(), RustyBotError> {
+        let mut problems = Vec::new();
+        let mut check_token = |name: &str, env_var: &str, token: &str, prefix: &str| {
+            if token.is_empty() {
+                problems.push(format!(
+                    "slack.{} is missing, set it in the config file or with the {} env var",
+                    name, env_var
+                ));
+            } else if !token.starts_with(prefix) {
+                problems.push(format!(
+                    "slack.{} should start with `{}`, check you didn't swap the bot and app tokens",
+                    name, prefix
+                ));
+            }
+        };
+        check_token(
+            "bot_token",
+            "SLACK_BOT_TOKEN",
+            &self.slack.bot_token,
+            "xoxb-",
+        );
+        match self.slack.mode {
+            SlackMode::Socket => check_token(
+                "app_token",
+                "SLACK_APP_TOKEN",
+                &self.slack.app_token,
+                "xapp-",
+            ),
+            SlackMode::Http => {
+                if self.slack.signing_secret.is_empty() {
+                    problems.push("slack.signing_secret is missing, it is required in http mode, set it in the config file or with the SLACK_SIGNING_SECRET env var".to_owned());
+                }
+                if self.http.address.parse::<SocketAddr>().is_err() {
+                    problems.push(format!(
+                        "http.address `{}` should be an ip:port address",
+                        self.http.address
+                    ));
+                }
+            }
+        }
+        if self.monitoring.enabled && self.monitoring.address.parse::<SocketAddr>().is_err() {
+            problems.push(format!(
+                "monitoring.address `{}` should be an ip:port address",
+                self.monitoring.address
+            ));
+        }
+        let playground_urls = std::iter::once(("playground.url", &self.playground.url))
+            .chain(
+                self.playground
+                    .fallback_urls
+                    .iter()
+                    .map(|url| ("playground.fallback_urls", url)),
+            )
+            .chain(std::iter::once(("crates_io.url", &self.crates_io.url)));
+        for (setting, value) in playground_urls {
+            match Url::parse(value) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => problems.push(format!("{} `{}` is not an http(s) URL", setting, value)),
+            }
+        }
+        for (setting, supported, value) in [
+            ("channel", CHANNELS, &self.defaults.channel),
+            ("edition", EDITIONS, &self.defaults.edition),
+            ("mode", MODES, &self.defaults.mode),
+        ] {
+            if find_setting(supported, setting, value).is_err() {
+                problems.push(format!(
+                    "defaults.{} `{}` should be one of {}",
+                    setting,
+                    value,
+                    supported.join(", ")
+                ));
+            }
+        }
+        if let Err(e) = EnvFilter::try_new(&self.log_level) {
+            problems.push(format!("log_level `{}` is invalid: {}", self.log_level, e));
+        }
+        for (name, value) in [
+            (
+                "limits.session_ttl_secs",
+                self.limits.session_ttl_secs as usize,
+            ),
+            (
+                "limits.metadata_ttl_secs",
+                self.limits.metadata_ttl_secs as usize,
+            ),
+            ("limits.max_output_chars", self.limits.max_output_chars),
+            (
+                "playground.connect_timeout_secs",
+                self.playground.connect_timeout_secs as usize,
+            ),
+            (
+                "playground.request_timeout_secs",
+                self.playground.request_timeout_secs as usize,
+            ),
+            (
+                "playground.failure_threshold",
+                self.playground.failure_threshold as usize,
+            ),
+            (
+                "crates_io.request_timeout_secs",
+                self.crates_io.request_timeout_secs as usize,
+            ),
+            ("queue.workers", self.queue.workers),
+            (
+                "queue.job_timeout_secs",
+                self.queue.job_timeout_secs as usize,
+            ),
+        ] {
+            if value == 0 {
+                problems.push(format!("{} should be greater than 0", name));
+            }
+        }
+        let channel_commands = self.policy.channels.iter().map(|(channel, rules)| {
+            (
+                format!("policy.channels.{}.commands", channel),
+                &rules.commands,
+            )
+        });
+        for (setting, commands) in self
+            .policy
+            .commands
+            .iter()
+            .map(|commands| ("policy.commands".to_owned(), commands))
+            .chain(channel_commands)
+        {
+            for command in commands {
+                if !command_names().contains(&command.as_str()) {
+                    problems.push(format!(
+                        "{} has unknown command `{}`, use one of {}",
+                        setting,
+                        command,
+                        command_names().join(", ")
+                    ));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(RustyBotError::InvalidConfig(problems.join("\n")))
+        }
+    }
+
+    /// The playground base URL without a trailing slash
+    pub fn playground_url(&self) -> &str {
+        self.playground.url.trim_end_matches('/')
+    }
+
+    pub fn is_admin(&self, user: &str) -> bool {
+        self.admin_users.iter().any(|admin| admin == user)
+    }
+}
+
+/// The current configuration, which can be swapped for a reloaded one at runtime.
+/// Handlers take a snapshot with `current` so a reload never changes a config mid-request.
+#[derive(Debug)]
+pub struct SharedConfig {
+    current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.current.read().expect("config lock poisoned").clone()
+    }
+
+    /// Loads the configuration again and swaps it in. When it's invalid the error is
+    /// returned and the current configuration is kept.
+    pub fn reload(&self) -> Result<Arc<Config>, RustyBotError> {
+        let config = Arc::new(Config::load().map_err(|e| {
+            error!(
+                "Error: {}\n when reloading the configuration, keeping the current one",
+                e
+            );
+            e
+        })?);
+        *self.current.write().expect("config lock poisoned") = config.clone();
+        info!("Configuration reloaded");
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_CONFIG: &str = r#"
+log_level = "debug"
+
+[slack]
+bot_token = "xoxb-1"
+app_token = "xapp-1"
+mode = "socket"
+
+[defaults]
+channel = "nightly"
+
+[policy]
+allowed_channels = ["C1", "C2"]
+denied_channels = ["C2"]
+
+[policy.channels.C1]
+commands = ["eval", "help"]
+"#;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(VALID_CONFIG).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.defaults.channel, "nightly");
+        assert_eq!(config.defaults.edition, "2021");
+        assert_eq!(config.playground_url(), "https://play.rust-lang.org");
+        assert_eq!(config.policy.channels["C1"].commands, vec!["eval", "help"]);
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut policy = Config::from_toml(VALID_CONFIG).unwrap().policy;
+        assert_eq!(
+            policy.check("C1", false, Some("eval")),
+            PolicyDecision::Allow
+        );
+        assert_eq!(policy.check("C1", false, None), PolicyDecision::Allow);
+        assert_eq!(
+            policy.check("C1", false, Some("gist")),
+            PolicyDecision::CommandDisabled
+        );
+        assert_eq!(
+            policy.check("C2", false, Some("eval")),
+            PolicyDecision::Ignore
+        );
+        assert_eq!(
+            policy.check("C3", false, Some("eval")),
+            PolicyDecision::Ignore
+        );
+        assert_eq!(
+            policy.check("D1", true, Some("gist")),
+            PolicyDecision::Allow
+        );
+
+        policy.dm_only = true;
+        policy.commands = Some(vec!["code".to_owned()]);
+        assert_eq!(
+            policy.check("C1", false, Some("eval")),
+            PolicyDecision::DmOnly
+        );
+        assert_eq!(
+            policy.check("D1", true, Some("eval")),
+            PolicyDecision::CommandDisabled
+        );
+        assert_eq!(
+            policy.check("D1", true, Some("code")),
+            PolicyDecision::Allow
+        );
+        // a mention without a command
+        assert_eq!(policy.check("C1", false, None), PolicyDecision::DmOnly);
+        assert_eq!(policy.check("D1", true, None), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let error = Config::from_toml("[slack]\nbot_tokn = \"xoxb-1\"").unwrap_err();
+        assert!(error.to_string().contains("bot_tokn"));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = Config::from_toml(VALID_CONFIG).unwrap();
+        config.apply_env_overrides(|key| match key {
+            "PLAYGROUND_URL" => Some("http://localhost:5000/".to_owned()),
+            "PLAYGROUND_FALLBACK_URLS" => Some("https://play.rust-lang.org/".to_owned()),
+            "RUSTY_ALLOWED_CHANNELS" => Some("C2, C3".to_owned()),
+            "RUSTY_MODE" => Some("http".to_owned()),
+            "CRATES_IO_URL" => Some("http://localhost:8888/".to_owned()),
+            _ => None,
+        });
+        assert_eq!(config.slack.mode, SlackMode::Http);
+        assert_eq!(config.playground_url(), "http://localhost:5000");
+        assert_eq!(
+            config.playground.urls(),
+            vec!["http://localhost:5000", "https://play.rust-lang.org"]
+        );
+        assert_eq!(config.policy.allowed_channels, vec!["C2", "C3"]);
+        assert_eq!(config.crates_io.base_url(), "http://localhost:8888");
+        assert_eq!(config.slack.bot_token, "xoxb-1");
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::default();
+        config.slack.app_token = "xoxb-1".to_owned();
+        config.defaults.edition = "2020".to_owned();
+        config.policy.commands = Some(vec!["evaluate".to_owned()]);
+        config.playground.fallback_urls = vec!["play.rust-lang.org".to_owned()];
+        config.crates_io.url = "crates.io".to_owned();
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("slack.bot_token is missing"));
+        assert!(message.contains("slack.app_token should start with `xapp-`"));
+        assert!(message.contains("defaults.edition `2020` should be one of 2015, 2018, 2021"));
+        assert!(message.contains("policy.commands has unknown command `evaluate`"));
+        assert!(
+            message.contains("playground.fallback_urls `play.rust-lang.org` is not an http(s) URL")
+        );
+        assert!(message.contains("crates_io.url `crates.io` is not an http(s) URL"));
+
+        config.slack.mode = SlackMode::Http;
+        config.http.address = "localhost".to_owned();
+        let message = config.validate().unwrap_err().to_string();
+        assert!(!message.contains("slack.app_token"));
+        assert!(message.contains("slack.signing_secret is missing"));
+        assert!(message.contains("http.address `localhost` should be an ip:port address"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::CratesIoConfig;
+use crate::error::RustyBotError;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use tracing::{debug, error};
+
+/// What `/api/v1/crates/{name}` answers, only the crate itself is read
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct CrateInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub max_version: String,
+    /// Not set when the crate only has pre-releases
+    pub max_stable_version: Option<String>,
+    pub downloads: u64,
+    /// Downloads of the last 90 days
+    pub recent_downloads: Option<u64>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+    pub updated_at: String,
+}
+
+impl CrateInfo {
+    /// The latest stable version, the latest pre-release when there is none
+    pub fn latest_version(&self) -> &str {
+        self.max_stable_version
+            .as_deref()
+            .unwrap_or(&self.max_version)
+    }
+
+    pub fn docs_url(&self) -> String {
+        format!("https://docs.rs/{}/{}", self.name, self.latest_version())
+    }
+
+    /// The dependency line for the `[dependencies]` of a Cargo.toml
+    pub fn cargo_toml_line(&self) -> String {
+        format!("{} = \"{}\"", self.name, self.latest_version())
+    }
+}
+
+/// The key of a crate in the cache. crates.io doesn't tell `-` from `_` nor the case
+/// in crate names, so neither does the key.
+pub fn cache_key(name: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.to_lowercase().replace('_', "-").hash(&mut hasher);
+    hasher.finish()
+}
+
+/// HTTP client for the crates.io API, or any server with the same API. Like the
+/// playground client, it should be created once and shared.
+#[derive(Debug, Clone)]
+pub struct CratesIoClient {
+    http: Client,
+}
+
+impl CratesIoClient {
+    /// crates.io rejects requests without a user agent saying who is sending them
+    pub fn new(config: &CratesIoConfig) -> Result<Self, RustyBotError> {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout_secs))
+            .user_agent(config.user_agent.as_str())
+            .build()
+            .map_err(|e| RustyBotError::InternalServerError(e.into()))?;
+        Ok(Self { http })
+    }
+
+    pub async fn fetch_crate(
+        &self,
+        crates_io_url: &str,
+        name: &str,
+    ) -> Result<CrateInfo, RustyBotError> {
+        debug!(
+            "fetch_crate function start with base URL: {} and crate {}",
+            crates_io_url, name
+        );
+        let url = format!("{}/api/v1/crates/{}", crates_io_url, name);
+        let response = self.http.get(&url).send().await.map_err(|e| {
+            error!("Error: {}\n when sending request to {}", e, url);
+            RustyBotError::CratesIoUnavailable(e.to_string())
+        })?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(RustyBotError::CrateNotFound {
+                name: name.to_owned(),
+            });
+        }
+        let text = response.text().await.map_err(|e| {
+            error!("Error: {}\n when reading the response body of {}", e, url);
+            RustyBotError::CratesIoUnavailable(e.to_string())
+        })?;
+        if !status.is_success() {
+            error!(
+                "Error: status {}\n when sending request to {}: {}",
+                status, url, text
+            );
+            return Err(RustyBotError::CratesIoUnavailable(format!(
+                "crates.io answered with status {}",
+                status
+            )));
+        }
+        let crate_response: CrateResponse = serde_json::from_str(&text).map_err(|e| {
+            error!(
+                "Error: {}\n when deserializing the response body of {}: {}",
+                e, url, text
+            );
+            RustyBotError::CratesIoUnavailable(e.to_string())
+        })?;
+        Ok(crate_response.krate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crate_info(max_version: &str, max_stable_version: Option<&str>) -> CrateInfo {
+        CrateInfo {
+            name: "serde_json".to_owned(),
+            description: None,
+            max_version: max_version.to_owned(),
+            max_stable_version: max_stable_version.map(str::to_owned),
+            downloads: 0,
+            recent_downloads: None,
+            repository: None,
+            documentation: None,
+            homepage: None,
+            updated_at: "2022-01-01T00:00:00.000000+00:00".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_latest_version() {
+        let stable = crate_info("2.0.0-rc.1", Some("1.0.136"));
+        assert_eq!(stable.latest_version(), "1.0.136");
+        assert_eq!(stable.cargo_toml_line(), "serde_json = \"1.0.136\"");
+        assert_eq!(stable.docs_url(), "https://docs.rs/serde_json/1.0.136");
+        let prerelease = crate_info("0.1.0-alpha.1", None);
+        assert_eq!(prerelease.latest_version(), "0.1.0-alpha.1");
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(cache_key("serde_json"), cache_key("Serde-JSON"));
+        assert_ne!(cache_key("serde"), cache_key("serde_json"));
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long handled events are remembered, Slack stops retrying an event after a few minutes
+pub const SEEN_EVENTS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Keys of the events handled recently, so an event Slack delivers again (e.g. after a slow
+/// acknowledgement) is only handled once
+#[derive(Debug)]
+pub struct SeenEvents {
+    ttl: Duration,
+    seen: Mutex<HashMap<String, Instant>>,
+}
+
+impl Default for SeenEvents {
+    fn default() -> Self {
+        Self::new(SEEN_EVENTS_TTL)
+    }
+}
+
+impl SeenEvents {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the keys of an event, returns `false` when one of them was already seen
+    pub fn first_time(&self, keys: &[String]) -> bool {
+        let mut seen = self.seen.lock().expect("seen events lock poisoned");
+        let ttl = self.ttl;
+        seen.retain(|_, seen_at| seen_at.elapsed() < ttl);
+        let first_time = keys.iter().all(|key| !seen.contains_key(key));
+        let now = Instant::now();
+        for key in keys {
+            seen.insert(key.clone(), now);
+        }
+        first_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_time() {
+        let seen = SeenEvents::new(Duration::from_secs(60));
+        let keys = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        assert!(seen.first_time(&keys(&["event:Ev1", "message:C1:1.0"])));
+        assert!(!seen.first_time(&keys(&["event:Ev1", "message:C1:1.0"])));
+        // redelivered with another event id
+        assert!(!seen.first_time(&keys(&["event:Ev2", "message:C1:1.0"])));
+        assert!(seen.first_time(&keys(&["event:Ev3", "mention:C1:1.0"])));
+
+        let expired = SeenEvents::new(Duration::from_secs(0));
+        assert!(expired.first_time(&keys(&["event:Ev1"])));
+        assert!(expired.first_time(&keys(&["event:Ev1"])));
+    }
+}
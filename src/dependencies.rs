@@ -0,0 +1,92 @@
+use regex::Regex;
+use std::collections::BTreeSet;
+
+/// Paths that can be imported without any dependency
+const BUILTIN_ROOTS: &[&str] = &[
+    "std",
+    "core",
+    "alloc",
+    "proc_macro",
+    "test",
+    "crate",
+    "self",
+    "super",
+];
+
+/// Names of the third party crates a snippet imports through `use` or `extern crate`.
+/// Roots that name something declared in the snippet itself (`use Direction::*`) are skipped.
+pub fn find_external_crates(code: &str) -> Vec<String> {
+    let import_re = Regex::new(
+        r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?(?:use\s+(?:::)?|extern\s+crate\s+)(?P<root>[A-Za-z_][A-Za-z0-9_]*)",
+    )
+    .expect("import regex should not fail");
+    let declaration_re =
+        Regex::new(r"\b(?:mod|enum|struct|trait|type|union)\s+(?P<name>[A-Za-z_][A-Za-z0-9_]*)")
+            .expect("declaration regex should not fail");
+
+    let declared: BTreeSet<&str> = declaration_re
+        .captures_iter(code)
+        .filter_map(|capture| capture.name("name"))
+        .map(|name| name.as_str())
+        .collect();
+    import_re
+        .captures_iter(code)
+        .filter_map(|capture| capture.name("root"))
+        .map(|root| root.as_str())
+        .filter(|root| !BUILTIN_ROOTS.contains(root) && !declared.contains(root))
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .map(|root| root.to_owned())
+        .collect()
+}
+
+/// The crates from `used` that are not in `available`. Crate names are compared the way
+/// they appear in paths, with `-` as `_`.
+pub fn unsupported_crates(used: &[String], available: &[String]) -> Vec<String> {
+    used.iter()
+        .filter(|name| {
+            !available
+                .iter()
+                .any(|available| available.replace('-', "_") == name.replace('-', "_"))
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn unsupported_crates_message(unsupported: &[String], available: &[String]) -> String {
+    let quote = |names: &[String]| {
+        names
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    format!(
+        "This snippet uses crates that are not available: {}\n*Available crates*\n{}",
+        quote(unsupported),
+        quote(available)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_external_crates() {
+        let code = "extern crate rand;\nuse serde::{Serialize, Deserialize};\nuse ::serde_json::json;\nuse std::collections::HashMap;\nuse self::inner::f;\nmod inner { pub fn f() {} }\nenum Direction { Up }\nuse Direction::*;\npub(crate) use regex::Regex;";
+        assert_eq!(
+            find_external_crates(code),
+            vec!["rand", "regex", "serde", "serde_json"]
+        );
+        assert!(find_external_crates("println!(\"use std;\");").is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_crates() {
+        let used = vec!["rand".to_owned(), "my_crate".to_owned()];
+        let available = vec!["rand".to_owned(), "serde-json".to_owned()];
+        assert_eq!(unsupported_crates(&used, &available), vec!["my_crate"]);
+        assert!(unsupported_crates(&["serde_json".to_owned()], &available).is_empty());
+    }
+}
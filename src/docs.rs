@@ -0,0 +1,294 @@
+/// Index of the std, core and alloc items, generated by `scripts/generate_doc_index.py`
+const BUNDLED_INDEX: &str = include_str!("../data/std_docs.tsv");
+
+const STABLE_DOCS_URL: &str = "https://doc.rust-lang.org/";
+const NIGHTLY_DOCS_URL: &str = "https://doc.rust-lang.org/nightly/";
+
+/// Suggestions given when nothing matches a lookup
+const MAX_SUGGESTIONS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stability {
+    /// Stable since the given version
+    Stable(String),
+    /// Nightly only, behind the given feature
+    Unstable(String),
+    Deprecated,
+    Unknown,
+}
+
+impl Stability {
+    fn parse(value: &str) -> Self {
+        match value.split_once(':') {
+            Some(("stable", since)) => Stability::Stable(since.to_owned()),
+            Some(("unstable", feature)) => Stability::Unstable(feature.to_owned()),
+            _ if value == "deprecated" => Stability::Deprecated,
+            _ => Stability::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocItem {
+    /// e.g. `std::vec::Vec::retain`
+    pub path: String,
+    /// e.g. `struct` or `method`
+    pub kind: String,
+    pub stability: Stability,
+    pub signature: String,
+    /// The first paragraph of the docs
+    pub summary: String,
+    /// Path of the page on the docs site, with the anchor of the item
+    url_path: String,
+}
+
+impl DocItem {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let mut next = || fields.next().map(unescape);
+        Some(Self {
+            path: next()?,
+            kind: next()?,
+            stability: Stability::parse(&next()?),
+            url_path: next()?,
+            signature: next()?,
+            summary: next()?,
+        })
+    }
+
+    /// Unstable items are only documented on the nightly docs
+    pub fn url(&self) -> String {
+        match self.stability {
+            Stability::Unstable(_) => format!("{}{}", NIGHTLY_DOCS_URL, self.url_path),
+            _ => format!("{}{}", STABLE_DOCS_URL, self.url_path),
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.path.rsplit("::").next().unwrap_or_default()
+    }
+
+    /// The last `segments` segments of the path
+    fn suffix(&self, segments: usize) -> &str {
+        match self
+            .path
+            .rmatch_indices("::")
+            .nth(segments.saturating_sub(1))
+        {
+            Some((index, _)) => &self.path[index + 2..],
+            None => &self.path,
+        }
+    }
+
+    /// Orders the items matching the same query, std items and types first
+    fn rank(&self) -> (usize, usize, bool) {
+        let krate = match self.path.split("::").next() {
+            Some("std") => 0,
+            Some("core") => 1,
+            _ => 2,
+        };
+        (
+            krate,
+            self.path.matches("::").count(),
+            self.kind == "method",
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocLookup<'a> {
+    Found(&'a DocItem),
+    /// Items with a name close to the query
+    NotFound(Vec<&'a DocItem>),
+}
+
+/// The std, core and alloc items, searched by `!doc`
+#[derive(Debug, Clone, Default)]
+pub struct DocIndex {
+    items: Vec<DocItem>,
+}
+
+impl DocIndex {
+    pub fn parse(index: &str) -> Self {
+        Self {
+            items: index
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(DocItem::parse)
+                .collect(),
+        }
+    }
+
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_INDEX)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Finds the item at `query`, a full path (`std::vec::Vec`) or its end (`Vec::retain`)
+    pub fn lookup(&self, query: &str) -> DocLookup<'_> {
+        let query = query
+            .trim()
+            .trim_matches('`')
+            .trim_end_matches("()")
+            .replace('.', "::");
+        let lowercase = query.to_lowercase();
+        let segments = query.matches("::").count() + 1;
+        let exact = self.best(|item| item.suffix(segments) == query);
+        if let Some(item) =
+            exact.or_else(|| self.best(|item| item.suffix(segments).to_lowercase() == lowercase))
+        {
+            return DocLookup::Found(item);
+        }
+
+        let max_distance = (lowercase.chars().count() / 4).max(1);
+        let mut suggestions: Vec<(usize, &DocItem)> = self
+            .items
+            .iter()
+            .filter(|item| segments > 1 || item.kind != "method")
+            .map(|item| {
+                let distance = levenshtein(&item.suffix(segments).to_lowercase(), &lowercase);
+                (distance, item)
+            })
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        suggestions.sort_by_key(|(distance, item)| (*distance, item.rank()));
+        let mut names: Vec<&str> = Vec::new();
+        DocLookup::NotFound(
+            suggestions
+                .into_iter()
+                .map(|(_, item)| item)
+                // the same name can be in several modules
+                .filter(|item| {
+                    let name = item.suffix(segments);
+                    let new = !names.contains(&name);
+                    names.push(name);
+                    new && !item.name().is_empty()
+                })
+                .take(MAX_SUGGESTIONS)
+                .collect(),
+        )
+    }
+
+    fn best<P: Fn(&DocItem) -> bool>(&self, predicate: P) -> Option<&DocItem> {
+        self.items
+            .iter()
+            .filter(|item| predicate(item))
+            .min_by_key(|item| item.rank())
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// The number of characters to insert, remove or replace to turn `a` into `b`
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(index: &DocIndex, query: &str) -> String {
+        match index.lookup(query) {
+            DocLookup::Found(item) => item.path.clone(),
+            DocLookup::NotFound(suggestions) => panic!("{} not found: {:?}", query, suggestions),
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let index = DocIndex::bundled();
+        assert!(index.len() > 1000);
+        assert_eq!(found(&index, "Vec::retain"), "std::vec::Vec::retain");
+        assert_eq!(found(&index, "Iterator::fold"), "std::iter::Iterator::fold");
+        assert_eq!(found(&index, "`vec::Vec`"), "std::vec::Vec");
+        assert_eq!(found(&index, "Vec"), "std::vec::Vec");
+        assert_eq!(
+            found(&index, "hashmap::entry"),
+            "std::collections::HashMap::entry"
+        );
+        assert_eq!(found(&index, "Option.map()"), "std::option::Option::map");
+
+        let retain = match index.lookup("Vec::retain") {
+            DocLookup::Found(item) => item,
+            _ => unreachable!(),
+        };
+        assert_eq!(retain.kind, "method");
+        assert_eq!(retain.stability, Stability::Stable("1.0.0".to_owned()));
+        assert!(retain
+            .signature
+            .starts_with("pub fn retain<F>(&mut self, f: F)\nwhere"));
+        assert_eq!(
+            retain.url(),
+            "https://doc.rust-lang.org/std/vec/struct.Vec.html#method.retain"
+        );
+
+        match index.lookup("Vec::retian") {
+            DocLookup::NotFound(suggestions) => {
+                assert_eq!(suggestions[0].path, "std::vec::Vec::retain")
+            }
+            found => panic!("unexpected {:?}", found),
+        }
+        assert_eq!(
+            index.lookup("NoSuchThingAnywhere"),
+            DocLookup::NotFound(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let index = DocIndex::parse(
+            "# header\nstd::a::B\tstruct\tunstable:b_feature\tstd/a/struct.B.html\tpub struct B {\\n}\tA \\\\ B.\nbroken line\n",
+        );
+        assert_eq!(index.len(), 1);
+        let item = &index.items[0];
+        assert_eq!(item.stability, Stability::Unstable("b_feature".to_owned()));
+        assert_eq!(item.signature, "pub struct B {\n}");
+        assert_eq!(item.summary, "A \\ B.");
+        assert_eq!(
+            item.url(),
+            "https://doc.rust-lang.org/nightly/std/a/struct.B.html"
+        );
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("retain", "retain"), 0);
+        assert_eq!(levenshtein("retian", "retain"), 2);
+        assert_eq!(levenshtein("fold", "folds"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+}
@@ -6,9 +6,87 @@ pub enum RustyBotError {
     #[error(transparent)]
     InternalServerError(#[from] anyhow::Error),
     #[error(
-        "The command {command} is not a valid command for the bot. Use one of !code, !eval, !help (docs, book)"
+        "The command {command} is not a valid command for the bot. Use one of {}",
+        crate::commands::command_list()
     )]
     InvalidBotCommand { command: String },
+    #[error("Invalid configuration:\n{0}")]
+    InvalidConfig(String),
+    #[error("{value} is not a valid playground {setting}")]
+    InvalidPlaygroundSetting { setting: String, value: String },
+    #[error("The execution took longer than {secs} seconds and was stopped")]
+    ExecutionTimeout { secs: u64 },
+    #[error("Cancelled by <@{user}>")]
+    ExecutionCancelled { user: String },
+    #[error("The playground took too long to answer")]
+    PlaygroundTimeout,
+    #[error("The playground is unavailable: {0}")]
+    PlaygroundUpstream(String),
+    #[error("The playground sent a response that couldn't be read: {0}")]
+    PlaygroundMalformedResponse(String),
+    #[error("The playground is rate limiting the bot (retry after {retry_after:?} seconds)")]
+    PlaygroundRateLimited { retry_after: Option<u64> },
+    #[error("The playground rejected the request: {0}")]
+    PlaygroundError(String),
+    /// A 4xx status caused by the request, e.g. an unknown gist id
+    #[error("The playground answered the request with status {status}")]
+    PlaygroundRequestRejected { status: u16 },
+    #[error("There is no crate named `{name}` on crates.io")]
+    CrateNotFound { name: String },
+    #[error("crates.io is unavailable: {0}")]
+    CratesIoUnavailable(String),
+}
+
+impl RustyBotError {
+    /// The error as told to Slack users, without the internal details
+    pub fn user_message(&self) -> String {
+        match self {
+            RustyBotError::InternalServerError(_) => {
+                "Something went wrong on my side, please try again later.".to_owned()
+            }
+            RustyBotError::PlaygroundTimeout => {
+                "The playground took too long to answer, please try again later.".to_owned()
+            }
+            RustyBotError::PlaygroundUpstream(_) => {
+                "The playground is unavailable right now, please try again later.".to_owned()
+            }
+            RustyBotError::PlaygroundMalformedResponse(_) => {
+                "The playground sent an answer I couldn't read, please try again later.".to_owned()
+            }
+            RustyBotError::PlaygroundRateLimited {
+                retry_after: Some(secs),
+            } => format!(
+                "The playground is getting too many requests, please retry in {} seconds.",
+                secs
+            ),
+            RustyBotError::PlaygroundRateLimited { retry_after: None } => {
+                "The playground is getting too many requests, please retry in a minute.".to_owned()
+            }
+            RustyBotError::PlaygroundError(message) => {
+                format!("The playground couldn't run this: {}", message)
+            }
+            RustyBotError::PlaygroundRequestRejected { status } => format!(
+                "The playground couldn't handle this request (status {}).",
+                status
+            ),
+            RustyBotError::CratesIoUnavailable(_) => {
+                "crates.io is unavailable right now, please try again later.".to_owned()
+            }
+            e => e.to_string(),
+        }
+    }
+
+    /// Whether the error comes from the playground endpoint itself, so another endpoint
+    /// may succeed
+    pub fn is_endpoint_failure(&self) -> bool {
+        matches!(
+            self,
+            RustyBotError::PlaygroundTimeout
+                | RustyBotError::PlaygroundUpstream(_)
+                | RustyBotError::PlaygroundMalformedResponse(_)
+                | RustyBotError::PlaygroundRateLimited { .. }
+        )
+    }
 }
 
 impl From<SlackClientError> for RustyBotError {
@@ -0,0 +1,212 @@
+use crate::config::PlaygroundConfig;
+use crate::error::RustyBotError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Default)]
+struct EndpointStats {
+    requests: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    total_latency: Duration,
+    last_latency: Option<Duration>,
+}
+
+/// The state of an endpoint, as reported by `Endpoints::metrics`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointMetrics {
+    pub url: String,
+    pub requests: u64,
+    pub failures: u64,
+    /// Whether the circuit breaker currently keeps requests away from the endpoint
+    pub open: bool,
+    pub average_latency: Option<Duration>,
+    pub last_latency: Option<Duration>,
+}
+
+/// Spreads playground calls over the configured endpoints: they are tried in order, and
+/// one that fails `failure_threshold` times in a row is skipped for `circuit_open_secs`.
+/// The endpoints are read from the configuration on every call, their stats are kept by URL.
+#[derive(Debug, Default)]
+pub struct Endpoints {
+    stats: Mutex<HashMap<String, EndpointStats>>,
+}
+
+impl Endpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The endpoints to try, the ones with an open circuit are left out unless all of them
+    /// are open
+    fn candidates(&self, urls: &[String]) -> Vec<String> {
+        let stats = self.stats.lock().expect("endpoint stats lock poisoned");
+        let now = Instant::now();
+        let closed: Vec<String> = urls
+            .iter()
+            .filter(|url| {
+                !matches!(stats.get(*url), Some(EndpointStats { open_until: Some(until), .. }) if *until > now)
+            })
+            .cloned()
+            .collect();
+        if closed.is_empty() {
+            urls.to_vec()
+        } else {
+            closed
+        }
+    }
+
+    pub fn record_success(&self, url: &str, latency: Duration) {
+        let mut stats = self.stats.lock().expect("endpoint stats lock poisoned");
+        let endpoint = stats.entry(url.to_owned()).or_default();
+        if endpoint.open_until.is_some() {
+            info!("Playground endpoint {} is back, closing its circuit", url);
+        }
+        endpoint.requests += 1;
+        endpoint.consecutive_failures = 0;
+        endpoint.open_until = None;
+        endpoint.total_latency += latency;
+        endpoint.last_latency = Some(latency);
+    }
+
+    pub fn record_failure(&self, url: &str, latency: Duration, config: &PlaygroundConfig) {
+        let mut stats = self.stats.lock().expect("endpoint stats lock poisoned");
+        let endpoint = stats.entry(url.to_owned()).or_default();
+        endpoint.requests += 1;
+        endpoint.failures += 1;
+        endpoint.consecutive_failures += 1;
+        endpoint.total_latency += latency;
+        endpoint.last_latency = Some(latency);
+        if endpoint.consecutive_failures >= config.failure_threshold {
+            warn!(
+                "Playground endpoint {} failed {} times in a row, skipping it for {} seconds",
+                url, endpoint.consecutive_failures, config.circuit_open_secs
+            );
+            endpoint.open_until =
+                Some(Instant::now() + Duration::from_secs(config.circuit_open_secs));
+        }
+    }
+
+    /// Runs `call` with the URL of each endpoint in turn until one doesn't fail because of
+    /// the endpoint itself. Other errors (e.g. the playground rejecting the code) are
+    /// returned right away.
+    pub async fn call<T, F, Fut>(
+        &self,
+        config: &PlaygroundConfig,
+        call: F,
+    ) -> Result<T, RustyBotError>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, RustyBotError>>,
+    {
+        let mut last_error = None;
+        for url in self.candidates(&config.urls()) {
+            let started = Instant::now();
+            match call(url.clone()).await {
+                Err(e) if e.is_endpoint_failure() => {
+                    debug!(
+                        "Playground endpoint {} failed: {}, trying the next one",
+                        url, e
+                    );
+                    self.record_failure(&url, started.elapsed(), config);
+                    last_error = Some(e);
+                }
+                result => {
+                    self.record_success(&url, started.elapsed());
+                    return result;
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RustyBotError::PlaygroundUpstream("no playground endpoint is configured".to_owned())
+        }))
+    }
+
+    pub fn metrics(&self, urls: &[String]) -> Vec<EndpointMetrics> {
+        let stats = self.stats.lock().expect("endpoint stats lock poisoned");
+        let now = Instant::now();
+        urls.iter()
+            .map(|url| {
+                let endpoint = stats.get(url).cloned().unwrap_or_default();
+                EndpointMetrics {
+                    url: url.clone(),
+                    requests: endpoint.requests,
+                    failures: endpoint.failures,
+                    open: matches!(endpoint.open_until, Some(until) if until > now),
+                    average_latency: match endpoint.requests {
+                        0 => None,
+                        requests => Some(endpoint.total_latency / requests as u32),
+                    },
+                    last_latency: endpoint.last_latency,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PlaygroundConfig {
+        PlaygroundConfig {
+            url: "http://primary".to_owned(),
+            fallback_urls: vec!["http://fallback".to_owned()],
+            failure_threshold: 2,
+            ..PlaygroundConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let endpoints = Endpoints::new();
+        let config = config();
+        let urls = config.urls();
+        assert_eq!(endpoints.candidates(&urls), urls);
+
+        endpoints.record_failure("http://primary", Duration::from_millis(10), &config);
+        assert_eq!(endpoints.candidates(&urls), urls);
+        endpoints.record_failure("http://primary", Duration::from_millis(30), &config);
+        assert_eq!(endpoints.candidates(&urls), vec!["http://fallback"]);
+
+        let metrics = endpoints.metrics(&urls);
+        assert!(metrics[0].open);
+        assert_eq!(metrics[0].failures, 2);
+        assert_eq!(metrics[0].average_latency, Some(Duration::from_millis(20)));
+        assert_eq!(metrics[1].requests, 0);
+
+        // with every circuit open, all the endpoints are tried anyway
+        endpoints.record_failure("http://fallback", Duration::from_millis(10), &config);
+        endpoints.record_failure("http://fallback", Duration::from_millis(10), &config);
+        assert_eq!(endpoints.candidates(&urls), urls);
+
+        endpoints.record_success("http://primary", Duration::from_millis(10));
+        assert_eq!(endpoints.candidates(&urls), vec!["http://primary"]);
+    }
+
+    #[test]
+    fn test_call_fails_over() {
+        let endpoints = Endpoints::new();
+        let config = config();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = runtime.block_on(endpoints.call(&config, |url| async move {
+            match url.as_str() {
+                "http://primary" => Err(RustyBotError::PlaygroundTimeout),
+                _ => Ok(url),
+            }
+        }));
+        assert_eq!(result.unwrap(), "http://fallback");
+
+        let result: Result<(), _> = runtime.block_on(endpoints.call(&config, |_| async {
+            Err(RustyBotError::PlaygroundError("rejected".to_owned()))
+        }));
+        assert!(matches!(result, Err(RustyBotError::PlaygroundError(_))));
+        assert_eq!(endpoints.metrics(&config.urls())[1].requests, 1);
+    }
+}
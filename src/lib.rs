@@ -1,4 +1,21 @@
+pub mod books;
 pub mod bot;
+pub mod cache;
+pub mod commands;
+pub mod config;
+pub mod crates_io;
+pub mod dedup;
+pub mod dependencies;
+pub mod docs;
 pub mod error;
+pub mod failover;
+pub mod metrics;
 pub mod playground;
+pub mod queue;
+pub mod ratelimit;
+pub mod server;
+pub mod session;
+pub mod shutdown;
 pub mod slack_conn;
+pub mod socket_mode;
+pub mod state;
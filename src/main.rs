@@ -1,41 +1,63 @@
-use rusty_slackbot::bot::{error_handler, on_message};
+use rusty_slackbot::bot::{error_handler, resolve_bot_user_id, shut_down};
+use rusty_slackbot::config::{Config, SlackMode};
 use rusty_slackbot::error::RustyBotError;
+use rusty_slackbot::server::{serve_http, serve_monitoring};
+use rusty_slackbot::shutdown::shutdown_signal;
+use rusty_slackbot::socket_mode::serve_socket_mode;
+use rusty_slackbot::state::{check_playground_health, reload_config_on_sighup, BotState};
 use slack_morphism::prelude::*;
 use slack_morphism_hyper::*;
-use std::env;
+use std::process;
 use std::sync::Arc;
+use tracing::error;
 use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), RustyBotError> {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
     let (non_blocking_writer, _guard) = tracing_appender::non_blocking(std::io::stdout());
     tracing_subscriber::fmt()
         .with_writer(non_blocking_writer)
-        .with_env_filter(EnvFilter::new(
-            env::var("RUSTY_LOG_LEVEL").expect("RUSTY_LOG_LEVEL env var was not found"),
-        ))
+        .with_env_filter(EnvFilter::new(&config.log_level))
         .init();
 
     let client = Arc::new(SlackClient::new(SlackClientHyperConnector::new()));
+    let bot_user_id = resolve_bot_user_id(&client, &config).await;
+    let state = BotState::new(config)?.with_bot_user_id(bot_user_id);
+    let config = state.config.current();
+    #[cfg(unix)]
+    tokio::spawn(reload_config_on_sighup(state.clone()));
+    tokio::spawn(check_playground_health(state.clone()));
+    if config.monitoring.enabled {
+        let monitoring_state = state.clone();
+        let address = config.monitoring.address.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_monitoring(monitoring_state, &address).await {
+                error!("Error: {}\n when serving health checks and metrics", e);
+            }
+        });
+    }
 
-    let socket_mode_callbacks =
-        SlackSocketModeListenerCallbacks::new().with_push_events(on_message);
     let listener_environment = Arc::new(
-        SlackClientEventsListenerEnvironment::new(client.clone()).with_error_handler(error_handler),
-    );
-    let socket_mode_listener = SlackClientSocketModeListener::new(
-        &SlackClientSocketModeConfig::new(),
-        listener_environment.clone(),
-        socket_mode_callbacks,
+        SlackClientEventsListenerEnvironment::new(client.clone())
+            .with_error_handler(error_handler)
+            .with_user_state(state.clone()),
     );
 
-    let app_token_value =
-        SlackApiTokenValue(env::var("SLACK_APP_TOKEN").expect("SLACK_APP_TOKEN env var not found"));
-    let app_token = SlackApiToken::new(app_token_value);
-
-    socket_mode_listener.listen_for(&app_token).await?;
-
-    socket_mode_listener.serve().await;
+    match config.slack.mode {
+        SlackMode::Socket => {
+            serve_socket_mode(listener_environment, &config, shutdown_signal()).await?
+        }
+        SlackMode::Http => serve_http(listener_environment, &config, shutdown_signal()).await?,
+    }
+    shut_down(client, &state).await;
 
     Ok(())
 }
@@ -0,0 +1,336 @@
+use crate::error::RustyBotError;
+use crate::failover::EndpointMetrics;
+use crate::playground::PlaygroundAnswer;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the playground latency histogram buckets
+pub const LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// How a code execution ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExecutionOutcome {
+    Success,
+    CompileError,
+    /// The code compiled but the program failed, e.g. with a panic
+    RuntimeError,
+    Timeout,
+    Cancelled,
+    /// The playground or the bot failed to run it
+    Error,
+}
+
+impl ExecutionOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecutionOutcome::Success => "success",
+            ExecutionOutcome::CompileError => "compile_error",
+            ExecutionOutcome::RuntimeError => "runtime_error",
+            ExecutionOutcome::Timeout => "timeout",
+            ExecutionOutcome::Cancelled => "cancelled",
+            ExecutionOutcome::Error => "error",
+        }
+    }
+
+    pub fn of(result: &Result<PlaygroundAnswer, RustyBotError>) -> Self {
+        match result {
+            Ok(answer) if answer.playground_answer.success => ExecutionOutcome::Success,
+            Ok(answer)
+                if answer
+                    .playground_answer
+                    .stderr
+                    .contains("could not compile") =>
+            {
+                ExecutionOutcome::CompileError
+            }
+            Ok(_) => ExecutionOutcome::RuntimeError,
+            Err(RustyBotError::ExecutionTimeout { .. } | RustyBotError::PlaygroundTimeout) => {
+                ExecutionOutcome::Timeout
+            }
+            Err(RustyBotError::ExecutionCancelled { .. }) => ExecutionOutcome::Cancelled,
+            Err(_) => ExecutionOutcome::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    commands: BTreeMap<String, u64>,
+    executions: BTreeMap<ExecutionOutcome, u64>,
+    slack_api_errors: BTreeMap<String, u64>,
+    /// By endpoint and request
+    playground_latency: BTreeMap<(String, String), Histogram>,
+}
+
+/// The bot metrics, rendered in the Prometheus text format on `/metrics`
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+    slack_connected: AtomicBool,
+}
+
+/// Values read from the other components when the metrics are rendered
+#[derive(Debug, Clone, Default)]
+pub struct Gauges {
+    pub queue_depth: usize,
+    pub endpoints: Vec<EndpointMetrics>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn command(&self, command: &str) {
+        *self
+            .counters()
+            .commands
+            .entry(command.to_owned())
+            .or_default() += 1;
+    }
+
+    pub fn execution(&self, outcome: ExecutionOutcome) {
+        *self.counters().executions.entry(outcome).or_default() += 1;
+    }
+
+    pub fn slack_api_error(&self, method: &str) {
+        *self
+            .counters()
+            .slack_api_errors
+            .entry(method.to_owned())
+            .or_default() += 1;
+    }
+
+    /// Records the time a request to a playground endpoint took, failed ones included
+    pub fn playground_request(&self, endpoint: &str, request: &str, latency: Duration) {
+        self.counters()
+            .playground_latency
+            .entry((endpoint.to_owned(), request.to_owned()))
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    pub fn set_slack_connected(&self, connected: bool) {
+        self.slack_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn is_slack_connected(&self) -> bool {
+        self.slack_connected.load(Ordering::Relaxed)
+    }
+
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let counters = self.counters();
+        let mut out = String::new();
+        header(
+            &mut out,
+            "rusty_commands_total",
+            "counter",
+            "Commands received, by command",
+        );
+        for (command, count) in &counters.commands {
+            let _ = writeln!(
+                out,
+                "rusty_commands_total{{command=\"{}\"}} {}",
+                command, count
+            );
+        }
+        header(
+            &mut out,
+            "rusty_executions_total",
+            "counter",
+            "Code executions, by outcome",
+        );
+        for (outcome, count) in &counters.executions {
+            let _ = writeln!(
+                out,
+                "rusty_executions_total{{outcome=\"{}\"}} {}",
+                outcome.as_str(),
+                count
+            );
+        }
+        header(
+            &mut out,
+            "rusty_slack_api_errors_total",
+            "counter",
+            "Failed Slack API calls, by method",
+        );
+        for (method, count) in &counters.slack_api_errors {
+            let _ = writeln!(
+                out,
+                "rusty_slack_api_errors_total{{method=\"{}\"}} {}",
+                method, count
+            );
+        }
+        header(
+            &mut out,
+            "rusty_playground_request_duration_seconds",
+            "histogram",
+            "Duration of the requests to the playground, by endpoint and request",
+        );
+        for ((endpoint, request), histogram) in &counters.playground_latency {
+            let labels = format!("endpoint=\"{}\",request=\"{}\"", endpoint, request);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "rusty_playground_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rusty_playground_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "rusty_playground_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "rusty_playground_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+        header(
+            &mut out,
+            "rusty_playground_endpoint_up",
+            "gauge",
+            "Whether the circuit of the playground endpoint is closed",
+        );
+        for endpoint in &gauges.endpoints {
+            let _ = writeln!(
+                out,
+                "rusty_playground_endpoint_up{{endpoint=\"{}\"}} {}",
+                endpoint.url,
+                u8::from(!endpoint.open)
+            );
+        }
+        header(
+            &mut out,
+            "rusty_queue_depth",
+            "gauge",
+            "Snippets waiting for a worker",
+        );
+        let _ = writeln!(out, "rusty_queue_depth {}", gauges.queue_depth);
+        header(
+            &mut out,
+            "rusty_slack_connected",
+            "gauge",
+            "Whether the bot is connected to Slack",
+        );
+        let _ = writeln!(
+            out,
+            "rusty_slack_connected {}",
+            u8::from(self.is_slack_connected())
+        );
+        out
+    }
+
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.counters.lock().expect("metrics lock poisoned")
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playground::PlaygroundResponse;
+
+    #[test]
+    fn test_execution_outcome() {
+        let answer = |success: bool, stderr: &str| {
+            Ok(PlaygroundAnswer {
+                playground_answer: PlaygroundResponse {
+                    success,
+                    stdout: String::new(),
+                    stderr: stderr.to_owned(),
+                },
+                result: None,
+                link: None,
+                cached: false,
+            })
+        };
+        assert_eq!(
+            ExecutionOutcome::of(&answer(true, "")),
+            ExecutionOutcome::Success
+        );
+        assert_eq!(
+            ExecutionOutcome::of(&answer(false, "error: could not compile `playground`")),
+            ExecutionOutcome::CompileError
+        );
+        assert_eq!(
+            ExecutionOutcome::of(&answer(false, "thread 'main' panicked")),
+            ExecutionOutcome::RuntimeError
+        );
+        assert_eq!(
+            ExecutionOutcome::of(&Err(RustyBotError::ExecutionTimeout { secs: 60 })),
+            ExecutionOutcome::Timeout
+        );
+        assert_eq!(
+            ExecutionOutcome::of(&Err(RustyBotError::PlaygroundUpstream("502".to_owned()))),
+            ExecutionOutcome::Error
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.command("eval");
+        metrics.command("eval");
+        metrics.execution(ExecutionOutcome::CompileError);
+        metrics.slack_api_error("chat_update");
+        metrics.playground_request("http://p", "execute", Duration::from_millis(300));
+        metrics.playground_request("http://p", "execute", Duration::from_secs(100));
+        metrics.set_slack_connected(true);
+        let rendered = metrics.render(&Gauges {
+            queue_depth: 2,
+            endpoints: Vec::new(),
+        });
+        for line in [
+            "rusty_commands_total{command=\"eval\"} 2",
+            "rusty_executions_total{outcome=\"compile_error\"} 1",
+            "rusty_slack_api_errors_total{method=\"chat_update\"} 1",
+            "rusty_playground_request_duration_seconds_bucket{endpoint=\"http://p\",request=\"execute\",le=\"0.25\"} 0",
+            "rusty_playground_request_duration_seconds_bucket{endpoint=\"http://p\",request=\"execute\",le=\"0.5\"} 1",
+            "rusty_playground_request_duration_seconds_bucket{endpoint=\"http://p\",request=\"execute\",le=\"60\"} 1",
+            "rusty_playground_request_duration_seconds_bucket{endpoint=\"http://p\",request=\"execute\",le=\"+Inf\"} 2",
+            "rusty_playground_request_duration_seconds_count{endpoint=\"http://p\",request=\"execute\"} 2",
+            "rusty_queue_depth 2",
+            "rusty_slack_connected 1",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "missing {}", line);
+        }
+    }
+}
@@ -1,8 +1,17 @@
+use crate::config::PlaygroundConfig;
 use crate::error::RustyBotError;
+use crate::metrics::Metrics;
 use html_escape::decode_html_entities;
-use reqwest::Client;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
 use tracing::{debug, error};
 
 #[derive(Debug, Serialize, Clone)]
@@ -15,6 +24,9 @@ pub struct PlaygroundRequest {
     edition: &'static str,
     mode: &'static str,
     tests: bool,
+    /// The program as the user wrote it, shared instead of the generated `code`
+    #[serde(skip)]
+    source: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,6 +35,12 @@ pub struct Response {
     pub playground_response: PlaygroundResponse,
 }
 
+/// What the playground answers instead of the expected response when it rejects a request
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PlaygroundResponse {
     pub success: bool,
@@ -37,10 +55,276 @@ pub struct ShareResponse {
     pub url: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CrateInformation {
+    pub name: String,
+    pub version: String,
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CratesResponse {
+    pub crates: Vec<CrateInformation>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ToolVersion {
+    pub version: String,
+    pub hash: String,
+    pub date: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChannelVersions {
+    pub rustc: ToolVersion,
+    pub rustfmt: Option<ToolVersion>,
+    pub clippy: Option<ToolVersion>,
+    pub miri: Option<ToolVersion>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VersionsResponse {
+    pub stable: ChannelVersions,
+    pub beta: ChannelVersions,
+    pub nightly: ChannelVersions,
+}
+
 #[derive(Debug, Deserialize)]
+pub struct GistResponse {
+    pub id: String,
+    pub url: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct PlaygroundAnswer {
     pub playground_answer: PlaygroundResponse,
-    pub link: String,
+    pub result: Option<String>,
+    /// The share link, `None` when it wasn't wanted or couldn't be created
+    pub link: Option<String>,
+    /// Taken from the result cache instead of running the code
+    #[serde(default)]
+    pub cached: bool,
+}
+
+/// Printed to stdout right before the value of the trailing expression of an `!eval`
+/// snippet, so the value can be split from the rest of the output.
+pub const EVAL_RESULT_MARKER: &str = "\u{1f}rusty-result\u{1f}";
+
+/// Splits the stdout of an `!eval` run into the program's own output and the printed
+/// value of its trailing expression.
+pub fn split_eval_result(stdout: &str) -> (String, Option<String>) {
+    match stdout.rfind(EVAL_RESULT_MARKER) {
+        Some(index) => (
+            stdout[..index].to_owned(),
+            Some(
+                stdout[index + EVAL_RESULT_MARKER.len()..]
+                    .trim_end()
+                    .to_owned(),
+            ),
+        ),
+        None => (stdout.to_owned(), None),
+    }
+}
+
+/// Helpers prepended to `!eval` programs with a trailing expression. Values are printed
+/// with `{:#?}` when they implement `Debug` and fall back to their type name otherwise
+/// (autoref specialization picks the `Debug` impl first when it applies).
+fn eval_result_prelude() -> String {
+    format!(
+        r#"#[allow(dead_code, non_camel_case_types)]
+struct __RustyValue<T>(T);
+#[allow(dead_code, non_camel_case_types)]
+trait __RustyDebug {{ fn __rusty_print(&self); }}
+impl<T: std::fmt::Debug> __RustyDebug for __RustyValue<T> {{
+    fn __rusty_print(&self) {{
+        if std::any::type_name::<T>() != "()" {{
+            println!("{marker}{{:#?}}", self.0);
+        }}
+    }}
+}}
+#[allow(dead_code, non_camel_case_types)]
+trait __RustyNoDebug {{ fn __rusty_print(&self); }}
+impl<T> __RustyNoDebug for &__RustyValue<T> {{
+    fn __rusty_print(&self) {{
+        println!("{marker}<value of type `{{}}` does not implement Debug>", std::any::type_name::<T>());
+    }}
+}}
+"#,
+        marker = EVAL_RESULT_MARKER.escape_default()
+    )
+}
+
+/// Splits an `!eval` snippet into its statements and the trailing expression (the code
+/// after the last top level `;` or block that isn't terminated by a semicolon), if any.
+///
+/// Slack escapes `&`, `<` and `>` as HTML entities, so those are skipped as a whole rather
+/// than being read as a `;`. Snippets that don't scan cleanly (unbalanced delimiters,
+/// unterminated strings) are left alone so the compiler can report the real error.
+pub fn split_trailing_expression(code: &str) -> (&str, Option<&str>) {
+    let bytes = code.as_bytes();
+    let mut depth: i32 = 0;
+    let mut boundary = 0;
+    let mut tail_has_code = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &code[i..];
+        match bytes[i] {
+            b'/' if rest.starts_with("//") => {
+                i += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            }
+            b'/' if rest.starts_with("/*") => {
+                let mut nesting = 0;
+                let mut j = i;
+                while j < bytes.len() {
+                    if bytes[j..].starts_with(b"/*") {
+                        nesting += 1;
+                        j += 2;
+                    } else if bytes[j..].starts_with(b"*/") {
+                        nesting -= 1;
+                        j += 2;
+                        if nesting == 0 {
+                            break;
+                        }
+                    } else {
+                        j += 1;
+                    }
+                }
+                if nesting != 0 {
+                    return (code, None);
+                }
+                i = j;
+                continue;
+            }
+            b'&' if ["&amp;", "&lt;", "&gt;"]
+                .iter()
+                .any(|e| rest.starts_with(e)) =>
+            {
+                tail_has_code = true;
+                i += rest.find(';').unwrap_or(0) + 1;
+                continue;
+            }
+            b'"' => match find_string_end(code, i + 1, None) {
+                Some(end) => {
+                    tail_has_code = true;
+                    i = end;
+                    continue;
+                }
+                None => return (code, None),
+            },
+            b'r' if is_raw_string_start(code, i) => {
+                let hashes = rest[1..].bytes().take_while(|b| *b == b'#').count();
+                match find_string_end(code, i + hashes + 2, Some(hashes)) {
+                    Some(end) => {
+                        tail_has_code = true;
+                        i = end;
+                        continue;
+                    }
+                    None => return (code, None),
+                }
+            }
+            b'\'' => {
+                // char literals, as opposed to lifetimes and labels
+                let mut chars = rest[1..].chars();
+                match (chars.next(), chars.next()) {
+                    (Some('\\'), _) => match rest.get(3..).and_then(|r| r.find('\'')) {
+                        Some(end) => i += end + 4,
+                        None => return (code, None),
+                    },
+                    (Some(c), Some('\'')) => i += c.len_utf8() + 2,
+                    _ => i += 1,
+                }
+                tail_has_code = true;
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' => depth -= 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    boundary = i + 1;
+                    tail_has_code = false;
+                    i += 1;
+                    continue;
+                }
+            }
+            b';' if depth == 0 => {
+                boundary = i + 1;
+                tail_has_code = false;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        if depth < 0 {
+            return (code, None);
+        }
+        if !bytes[i].is_ascii_whitespace() {
+            tail_has_code = true;
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    if depth != 0 || !tail_has_code {
+        return (code, None);
+    }
+    let (statements, expression) = code.split_at(boundary);
+    let trimmed = expression.trim_start();
+    // continuations of a block (`else`, method calls) and unterminated `let`s can't be
+    // split off, leave them to the compiler
+    if ["else", ".", "?", "let "]
+        .iter()
+        .any(|p| trimmed.starts_with(p))
+    {
+        return (code, None);
+    }
+    (statements, Some(expression))
+}
+
+fn is_raw_string_start(code: &str, i: usize) -> bool {
+    let preceded_by_ident = matches!(
+        code[..i].chars().next_back(),
+        Some(c) if (c.is_alphanumeric() || c == '_') && c != 'b'
+    );
+    !preceded_by_ident && code[i + 1..].trim_start_matches('#').starts_with('"')
+}
+
+/// Returns the index right after the closing quote of a string literal whose content
+/// starts at `start`. `raw_hashes` is the number of `#` for raw strings, which have no escapes.
+fn find_string_end(code: &str, start: usize, raw_hashes: Option<usize>) -> Option<usize> {
+    let bytes = code.as_bytes();
+    let hashes = raw_hashes.unwrap_or(0);
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if raw_hashes.is_none() => i += 2,
+            b'"' if bytes[i + 1..].iter().take_while(|b| **b == b'#').count() >= hashes => {
+                return Some(i + 1 + hashes);
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+pub const CHANNELS: &[&str] = &["stable", "beta", "nightly"];
+pub const MODES: &[&str] = &["debug", "release"];
+pub const EDITIONS: &[&str] = &["2015", "2018", "2021"];
+
+/// Looks `value` up in the values supported by the playground for `setting`
+pub fn find_setting(
+    supported: &[&'static str],
+    setting: &str,
+    value: &str,
+) -> Result<&'static str, RustyBotError> {
+    supported
+        .iter()
+        .find(|supported| **supported == value)
+        .copied()
+        .ok_or_else(|| RustyBotError::InvalidPlaygroundSetting {
+            setting: setting.to_owned(),
+            value: value.to_owned(),
+        })
 }
 
 impl PlaygroundRequest {
@@ -48,6 +332,7 @@ impl PlaygroundRequest {
         Self {
             backtrace: false,
             channel: "stable",
+            source: code.clone(),
             code,
             crate_type: "bin",
             edition: "2021",
@@ -69,11 +354,30 @@ impl PlaygroundRequest {
     }
 
     pub fn new_eval(code: String) -> Self {
-        let code_to_eval = format!("fn main() {{{}}}", code);
+        let (code_to_eval, source) = match split_trailing_expression(&code) {
+            (statements, Some(expression)) => (
+                format!(
+                    "{}fn main() {{{}\n(&__RustyValue(\n{}\n)).__rusty_print();\n}}",
+                    eval_result_prelude(),
+                    statements,
+                    expression
+                ),
+                format!(
+                    "fn main() {{{}\nprintln!(\"{{:#?}}\", {});\n}}",
+                    statements,
+                    expression.trim()
+                ),
+            ),
+            _ => {
+                let program = format!("fn main() {{{}}}", code);
+                (program.clone(), program)
+            }
+        };
         Self {
             backtrace: false,
             channel: "stable",
             code: code_to_eval,
+            source,
             crate_type: "bin",
             edition: "2021",
             mode: "debug",
@@ -81,11 +385,50 @@ impl PlaygroundRequest {
         }
     }
 
+    pub fn with_channel(self, channel: &str) -> Result<Self, RustyBotError> {
+        let channel = find_setting(CHANNELS, "channel", channel)?;
+        Ok(Self { channel, ..self })
+    }
+
+    pub fn with_mode(self, mode: &str) -> Result<Self, RustyBotError> {
+        let mode = find_setting(MODES, "mode", mode)?;
+        Ok(Self { mode, ..self })
+    }
+
+    pub fn with_edition(self, edition: &str) -> Result<Self, RustyBotError> {
+        let edition = find_setting(EDITIONS, "edition", edition)?;
+        Ok(Self { edition, ..self })
+    }
+
+    pub fn get_mode(&self) -> String {
+        self.mode.to_owned()
+    }
+
+    /// Identifies the requests giving the same result: the code, without the surrounding
+    /// and trailing whitespace, and the settings
+    pub fn cache_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for line in self.code.trim().lines() {
+            line.trim_end().hash(&mut hasher);
+        }
+        (
+            self.backtrace,
+            self.channel,
+            self.crate_type,
+            self.edition,
+            self.mode,
+            self.tests,
+        )
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
     pub fn escape_html(&self) -> Self {
         Self {
             backtrace: self.backtrace,
             channel: self.channel,
             code: decode_html_entities(&self.code).as_ref().to_owned(),
+            source: decode_html_entities(&self.source).as_ref().to_owned(),
             crate_type: self.crate_type,
             edition: self.edition,
             mode: self.mode,
@@ -93,72 +436,319 @@ impl PlaygroundRequest {
         }
     }
 
-    pub async fn execute(&self, playground_url: &str) -> Result<Response, RustyBotError> {
-        debug!("execute function start with base URL: {}", playground_url);
-        let response = Client::new()
-            .post(format!("{}/execute", playground_url))
-            .json(&self)
-            .send()
-            .await
-            .map_err(|e| {
-                error!(
-                    "Error: {}\nwhen sending request to base URL {} with payload {:?}",
-                    e, playground_url, &self
-                );
-                RustyBotError::InternalServerError(e.into())
+    /// The playground link to the gist `gist_id` opened with this request's settings
+    pub fn share_link(&self, gist_id: &str) -> String {
+        format!(
+            "https://play.rust-lang.org/?version={}&mode={}&edition={}&gist={}",
+            self.channel, self.mode, self.edition, gist_id
+        )
+    }
+}
+
+/// A gist to run along with the settings found in its playground link
+#[derive(Debug, Clone, PartialEq)]
+pub struct GistLink {
+    pub id: String,
+    pub channel: Option<String>,
+    pub mode: Option<String>,
+    pub edition: Option<String>,
+}
+
+/// Parses a playground share link (`https://play.rust-lang.org/?version=...&gist=...`) or a
+/// bare gist id. Links may come wrapped in `<...>` and HTML escaped as Slack sends them.
+pub fn parse_gist_link(input: &str) -> Option<GistLink> {
+    let input = decode_html_entities(input.trim());
+    let input = input.trim_start_matches('<').trim_end_matches('>');
+    let input = input.split('|').next().unwrap_or_default();
+    if input.starts_with("http://") || input.starts_with("https://") {
+        let url = Url::parse(input).ok()?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        Some(GistLink {
+            id: param("gist")?,
+            channel: param("version"),
+            mode: param("mode"),
+            edition: param("edition"),
+        })
+    } else if !input.is_empty() && input.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(GistLink {
+            id: input.to_owned(),
+            channel: None,
+            mode: None,
+            edition: None,
+        })
+    } else {
+        None
+    }
+}
+
+/// HTTP client for the playground API. It keeps a pool of connections, so it should be
+/// created once and shared (it is cheap to clone). GET requests failing with a network
+/// error or a 5xx status are retried with an exponential backoff, POST requests only when
+/// they couldn't connect.
+#[derive(Debug, Clone)]
+pub struct PlaygroundClient {
+    http: Client,
+    max_retries: u32,
+    retry_backoff: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl PlaygroundClient {
+    pub fn new(config: &PlaygroundConfig) -> Result<Self, RustyBotError> {
+        let http = Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.request_timeout_secs))
+            .user_agent(config.user_agent.as_str())
+            .build()
+            .map_err(|e| RustyBotError::InternalServerError(e.into()))?;
+        Ok(Self {
+            http,
+            max_retries: config.max_retries,
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+            metrics: Arc::new(Metrics::new()),
+        })
+    }
+
+    /// Records the request latencies in `metrics` instead of the client's own
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sends the request made by `build`, retrying GETs on network errors and 5xx statuses
+    /// other than the playground's `{"error": ...}` payloads.
+    /// POSTs are only retried when the connection failed, as the playground may have run
+    /// the code or created the gist already. Timeouts are not retried, the playground is
+    /// most likely still busy with the request.
+    /// The latency of every attempt is recorded under `playground_url` and `request`.
+    async fn send<F>(
+        &self,
+        playground_url: &str,
+        request: &str,
+        what: &str,
+        build: F,
+    ) -> Result<reqwest::Response, RustyBotError>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let http_request = build(&self.http).build().map_err(|e| {
+                error!("Error: {}\n when building request to {}", e, what);
+                request_error(e)
             })?;
+            let idempotent = http_request.method() == Method::GET;
+            let started = Instant::now();
+            let result = self.http.execute(http_request).await;
+            self.metrics
+                .playground_request(playground_url, request, started.elapsed());
+            let last_attempt = attempt >= self.max_retries;
+            match result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.trim().parse().ok());
+                    error!(
+                        "Error: rate limited (retry after {:?})\n when sending request to {}",
+                        retry_after, what
+                    );
+                    return Err(RustyBotError::PlaygroundRateLimited { retry_after });
+                }
+                Ok(response) if response.status().is_server_error() => {
+                    let status = response.status();
+                    // the playground rejects requests with an `{"error": ...}` payload and a
+                    // 500, sending them again would only fail the same way
+                    let text = response.text().await.unwrap_or_default();
+                    if let Ok(ErrorResponse { error }) = serde_json::from_str(&text) {
+                        error!(
+                            "Error: {}\n returned by the playground with status {} for {}",
+                            error, status, what
+                        );
+                        return Err(RustyBotError::PlaygroundError(error));
+                    }
+                    if !idempotent || last_attempt {
+                        error!(
+                            "Error: status {}\n when sending request to {}: {}",
+                            status, what, text
+                        );
+                        return Err(RustyBotError::PlaygroundUpstream(format!(
+                            "the playground answered with status {}",
+                            status
+                        )));
+                    }
+                }
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    let retry = e.is_connect() || (idempotent && !e.is_timeout());
+                    if !retry || last_attempt {
+                        error!("Error: {}\n when sending request to {}", e, what);
+                        return Err(request_error(e));
+                    }
+                }
+            }
+            let backoff = self.retry_backoff * 2u32.pow(attempt);
+            attempt += 1;
+            debug!(
+                "Retrying request to {} in {:?} (attempt {})",
+                what, backoff, attempt
+            );
+            sleep(backoff).await;
+        }
+    }
+
+    /// Reads the body of `response` as JSON. The `{"error": ...}` payloads the playground
+    /// sends for requests it rejects, and other unsuccessful statuses, are turned into errors:
+    /// 4xx statuses are caused by the request, the others by the playground.
+    async fn parse<T: DeserializeOwned>(
+        what: &str,
+        response: reqwest::Response,
+    ) -> Result<T, RustyBotError> {
+        let status = response.status();
+        let text = response.text().await.map_err(|e| {
+            error!("Error: {}\n when reading the response body of {}", e, what);
+            request_error(e)
+        })?;
+        if let Ok(ErrorResponse { error }) = serde_json::from_str(&text) {
+            error!(
+                "Error: {}\n returned by the playground with status {} for {}",
+                error, status, what
+            );
+            return Err(RustyBotError::PlaygroundError(error));
+        }
+        if status.is_client_error() {
+            error!(
+                "Error: status {}\n when sending request to {}: {}",
+                status, what, text
+            );
+            return Err(RustyBotError::PlaygroundRequestRejected {
+                status: status.as_u16(),
+            });
+        }
+        if !status.is_success() {
+            error!(
+                "Error: status {}\n when sending request to {}: {}",
+                status, what, text
+            );
+            return Err(RustyBotError::PlaygroundUpstream(format!(
+                "the playground answered with status {}",
+                status
+            )));
+        }
+        serde_json::from_str(&text).map_err(|e| {
+            error!(
+                "Error: {}\n when deserializing the response body of {}: {}",
+                e, what, text
+            );
+            RustyBotError::PlaygroundMalformedResponse(e.to_string())
+        })
+    }
+
+    pub async fn execute(
+        &self,
+        playground_url: &str,
+        request: &PlaygroundRequest,
+    ) -> Result<Response, RustyBotError> {
+        debug!("execute function start with base URL: {}", playground_url);
+        let url = format!("{}/execute", playground_url);
+        let response = self
+            .send(playground_url, "execute", &url, |client| {
+                client.post(&url).json(request)
+            })
+            .await?;
         let status_code = response.status().as_str().to_owned();
-        let playground_response: PlaygroundResponse = serde_json::from_str(
-            &response
-                .text()
-                .await
-                .map_err(|e| {
-                    error!("Error: {}\n when trying to retreive body text of response in execute function to base URL: {}", e, playground_url);
-                    RustyBotError::InternalServerError(e.into())})?,
-        )
-        .map_err(|e| {
-            error!("Error: {}\n when trying to convert response body text to JSON format in execute function to base URL: {}", e, playground_url);
-            RustyBotError::InternalServerError(e.into())})?;
-        let ans = Response {
+        let playground_response: PlaygroundResponse = Self::parse(&url, response).await?;
+        Ok(Response {
             status_code,
             playground_response,
-        };
-        Ok(ans)
+        })
     }
 
-    pub async fn create_share_link(&self, playground_url: &str) -> Result<String, RustyBotError> {
+    pub async fn create_share_link(
+        &self,
+        playground_url: &str,
+        request: &PlaygroundRequest,
+    ) -> Result<String, RustyBotError> {
         debug!(
             "create_share_link function start with base URL: {}",
             playground_url
         );
-        let share_response: ShareResponse = Client::new()
-            .post(format!("{}/meta/gist/", playground_url))
-            .json(&json!({"code": self.code}))
-            .send()
-            .await
-            .map_err(|e| {
-                error!(
-                    "Error: {}\n when sending request to create share link to base URL {} with payload {:?}",
-                    e, playground_url,
-                    json!({"code": self.code})
-                );
-                RustyBotError::InternalServerError(e.into())
-            })?
-            .json()
-            .await
-            .map_err(|e| {
-                error!("Error: {}\n when trying to deserialize response of create_share_link to ShareResponse. base URL of request: {}", e, playground_url);
-                RustyBotError::InternalServerError(e.into())})?;
+        let url = format!("{}/meta/gist/", playground_url);
+        let payload = json!({"code": request.source});
+        let response = self
+            .send(playground_url, "share", &url, |client| {
+                client.post(&url).json(&payload)
+            })
+            .await?;
+        let share_response: ShareResponse = Self::parse(&url, response).await?;
+        let link = request.share_link(&share_response.id);
+        debug!("Share link produced: {}", link);
+        Ok(link)
+    }
+
+    /// Fetches the code of a gist created by the playground
+    pub async fn fetch_gist(
+        &self,
+        playground_url: &str,
+        gist_id: &str,
+    ) -> Result<GistResponse, RustyBotError> {
         debug!(
-            "Share link produced: {}",
-            format!(
-                "https://play.rust-lang.org/?version={}&mode=debug&edition={}&gist={}",
-                self.channel, self.edition, share_response.id
-            )
+            "fetch_gist function start with base URL: {} and gist id: {}",
+            playground_url, gist_id
         );
-        Ok(format!(
-            "https://play.rust-lang.org/?version={}&mode=debug&edition={}&gist={}",
-            self.channel, self.edition, share_response.id
-        ))
+        let url = format!("{}/meta/gist/{}", playground_url, gist_id);
+        let response = self
+            .send(playground_url, "gist", &url, |client| client.get(&url))
+            .await?;
+        Self::parse(&url, response).await
+    }
+
+    /// Fetches the crates available to code running on the playground
+    pub async fn fetch_crates(
+        &self,
+        playground_url: &str,
+    ) -> Result<CratesResponse, RustyBotError> {
+        debug!(
+            "fetch_crates function start with base URL: {}",
+            playground_url
+        );
+        let url = format!("{}/meta/crates", playground_url);
+        let response = self
+            .send(playground_url, "crates", &url, |client| client.get(&url))
+            .await?;
+        let crates_response: CratesResponse = Self::parse(&url, response).await?;
+        debug!("Fetched {} crates", crates_response.crates.len());
+        Ok(crates_response)
+    }
+
+    /// Fetches the versions of the toolchains installed on the playground for every channel
+    pub async fn fetch_versions(
+        &self,
+        playground_url: &str,
+    ) -> Result<VersionsResponse, RustyBotError> {
+        debug!(
+            "fetch_versions function start with base URL: {}",
+            playground_url
+        );
+        let url = format!("{}/meta/versions", playground_url);
+        let response = self
+            .send(playground_url, "versions", &url, |client| client.get(&url))
+            .await?;
+        let versions_response: VersionsResponse = Self::parse(&url, response).await?;
+        debug!("Fetched versions {:?}", versions_response);
+        Ok(versions_response)
+    }
+}
+
+fn request_error(e: reqwest::Error) -> RustyBotError {
+    if e.is_timeout() {
+        RustyBotError::PlaygroundTimeout
+    } else {
+        RustyBotError::PlaygroundUpstream(e.to_string())
     }
 }
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+
+/// Returned when `max_depth` jobs are already waiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull;
+
+/// Who queued a job and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobOwner {
+    pub user: Option<String>,
+    pub channel: String,
+}
+
+#[derive(Debug)]
+struct Job {
+    owner: JobOwner,
+    cancel: Option<oneshot::Sender<String>>,
+}
+
+/// Code executions waiting for one of the workers. Jobs start in the order they were
+/// queued and at most `workers` of them run at the same time. Queued and running jobs
+/// can be cancelled until their ticket is dropped.
+#[derive(Debug)]
+pub struct ExecutionQueue {
+    workers: Arc<Semaphore>,
+    waiting: Mutex<VecDeque<u64>>,
+    jobs: Mutex<HashMap<u64, Job>>,
+    next_id: AtomicU64,
+}
+
+/// A place in the queue. The worker is held until the ticket is dropped, and dropping a
+/// ticket that is still waiting leaves the queue.
+#[derive(Debug)]
+pub struct Ticket<'a> {
+    queue: &'a ExecutionQueue,
+    id: u64,
+    position: usize,
+    permit: Option<OwnedSemaphorePermit>,
+    cancelled: Option<oneshot::Receiver<String>>,
+}
+
+/// Resolves with the user who cancelled the job
+#[derive(Debug)]
+pub struct Cancellation(Option<oneshot::Receiver<String>>);
+
+impl Cancellation {
+    pub async fn wait(self) -> String {
+        match self.0 {
+            Some(cancelled) => match cancelled.await {
+                Ok(user) => user,
+                // the job is done, it can't be cancelled anymore
+                Err(_) => std::future::pending().await,
+            },
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl ExecutionQueue {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(workers)),
+            waiting: Mutex::new(VecDeque::new()),
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn enqueue(&self, max_depth: usize, owner: JobOwner) -> Result<Ticket<'_>, QueueFull> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut waiting = self.waiting.lock().expect("queue lock poisoned");
+        // a free worker goes to the jobs already waiting first
+        let permit = if waiting.is_empty() {
+            self.workers.clone().try_acquire_owned().ok()
+        } else {
+            None
+        };
+        let position = match permit {
+            Some(_) => 0,
+            None if waiting.len() >= max_depth => return Err(QueueFull),
+            None => {
+                waiting.push_back(id);
+                waiting.len()
+            }
+        };
+        let (cancel, cancelled) = oneshot::channel();
+        self.jobs.lock().expect("queue lock poisoned").insert(
+            id,
+            Job {
+                owner,
+                cancel: Some(cancel),
+            },
+        );
+        Ok(Ticket {
+            queue: self,
+            id,
+            position,
+            permit,
+            cancelled: Some(cancelled),
+        })
+    }
+
+    pub fn owner(&self, id: u64) -> Option<JobOwner> {
+        self.jobs
+            .lock()
+            .expect("queue lock poisoned")
+            .get(&id)
+            .map(|job| job.owner.clone())
+    }
+
+    /// The last job `user` queued in `channel` that is still queued or running
+    pub fn latest_job(&self, user: &str, channel: &str) -> Option<u64> {
+        self.jobs
+            .lock()
+            .expect("queue lock poisoned")
+            .iter()
+            .filter(|(_, job)| {
+                job.owner.user.as_deref() == Some(user) && job.owner.channel == channel
+            })
+            .map(|(id, _)| *id)
+            .max()
+    }
+
+    /// Cancels a queued or running job on behalf of `user`. Returns `false` when the job
+    /// is already done or cancelled.
+    pub fn cancel(&self, id: u64, user: &str) -> bool {
+        self.jobs
+            .lock()
+            .expect("queue lock poisoned")
+            .get_mut(&id)
+            .and_then(|job| job.cancel.take())
+            .map(|cancel| cancel.send(user.to_owned()).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Number of jobs waiting for a worker
+    pub fn len(&self) -> usize {
+        self.waiting.lock().expect("queue lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn leave(&self, id: u64) {
+        self.waiting
+            .lock()
+            .expect("queue lock poisoned")
+            .retain(|waiting| *waiting != id);
+    }
+}
+
+impl<'a> Ticket<'a> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Position in the queue when the job was queued, 0 when it could start right away
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Resolves when the job is cancelled, can only be taken once
+    pub fn cancellation(&mut self) -> Cancellation {
+        Cancellation(self.cancelled.take())
+    }
+
+    /// Waits until a worker is free for the job
+    pub async fn wait_turn(&mut self) {
+        if self.permit.is_none() {
+            let permit = self
+                .queue
+                .workers
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("the queue semaphore is never closed");
+            self.permit = Some(permit);
+            self.queue.leave(self.id);
+        }
+    }
+}
+
+impl<'a> Drop for Ticket<'a> {
+    fn drop(&mut self) {
+        if self.permit.is_none() {
+            self.queue.leave(self.id);
+        }
+        self.queue
+            .jobs
+            .lock()
+            .expect("queue lock poisoned")
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(user: &str) -> JobOwner {
+        JobOwner {
+            user: Some(user.to_owned()),
+            channel: "C1".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_queue_positions() {
+        let queue = ExecutionQueue::new(1);
+        let running = queue.enqueue(2, owner("U1")).unwrap();
+        assert_eq!(running.position(), 0);
+        let second = queue.enqueue(2, owner("U1")).unwrap();
+        let third = queue.enqueue(2, owner("U1")).unwrap();
+        assert_eq!((second.position(), third.position()), (1, 2));
+        assert_eq!(queue.enqueue(2, owner("U1")).unwrap_err(), QueueFull);
+
+        drop(second);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.enqueue(2, owner("U1")).unwrap().position(), 2);
+
+        drop(third);
+        drop(running);
+        assert!(queue.is_empty());
+        assert_eq!(queue.enqueue(0, owner("U1")).unwrap().position(), 0);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let queue = ExecutionQueue::new(1);
+        let mut first = queue.enqueue(2, owner("U1")).unwrap();
+        let second = queue.enqueue(2, owner("U2")).unwrap();
+        assert_eq!(queue.latest_job("U1", "C1"), Some(first.id()));
+        assert_eq!(queue.latest_job("U1", "C2"), None);
+        assert_eq!(queue.owner(second.id()), Some(owner("U2")));
+
+        let mut cancellation = first.cancellation().0.unwrap();
+        assert!(queue.cancel(first.id(), "U3"));
+        assert!(!queue.cancel(first.id(), "U3"));
+        assert_eq!(cancellation.try_recv().unwrap(), "U3");
+
+        let id = second.id();
+        drop(second);
+        assert!(queue.owner(id).is_none());
+        assert!(!queue.cancel(id, "U2"));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::config::{RateLimitConfig, RateLimitsConfig};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The limit that was hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitScope {
+    User,
+    Channel,
+    Global,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    pub scope: RateLimitScope,
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    pub fn message(&self) -> String {
+        let secs = self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0);
+        let who = match self.scope {
+            RateLimitScope::User => "You are running code too often",
+            RateLimitScope::Channel => "This channel is running code too often",
+            RateLimitScope::Global => "I'm running too much code right now",
+        };
+        format!("{}, please retry in {} seconds.", who, secs.max(1))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimitConfig, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimitConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate(limit)).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+
+    /// How long until a token is available, `None` if there is one already
+    fn wait(&self, limit: &RateLimitConfig) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / rate(limit)))
+        }
+    }
+
+    fn is_full(&self, limit: &RateLimitConfig) -> bool {
+        self.tokens >= f64::from(limit.burst)
+    }
+}
+
+/// Tokens added per second
+fn rate(limit: &RateLimitConfig) -> f64 {
+    f64::from(limit.per_minute) / 60.0
+}
+
+#[derive(Debug)]
+struct Buckets {
+    users: HashMap<String, Bucket>,
+    channels: HashMap<String, Bucket>,
+    global: Option<Bucket>,
+}
+
+/// Token buckets limiting the code executions per user, per channel and overall.
+/// The limits are passed on every check so a reloaded configuration applies right away.
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                users: HashMap::new(),
+                channels: HashMap::new(),
+                global: None,
+            }),
+        }
+    }
+
+    /// Takes a token from every bucket the execution counts against, or none of them
+    /// when one is empty. `user` is `None` for messages that were not sent by a user.
+    pub fn check(
+        &self,
+        limits: &RateLimitsConfig,
+        user: Option<&str>,
+        channel: &str,
+    ) -> Result<(), RateLimited> {
+        self.check_at(limits, user, channel, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        limits: &RateLimitsConfig,
+        user: Option<&str>,
+        channel: &str,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        let Buckets {
+            users,
+            channels,
+            global,
+        } = &mut *buckets;
+        // full buckets are the same as missing ones, drop them to keep the maps small
+        for (map, limit) in [
+            (&mut *users, &limits.user),
+            (&mut *channels, &limits.channel),
+        ] {
+            map.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                !bucket.is_full(limit)
+            });
+        }
+
+        let mut selected = Vec::new();
+        if limits.user.is_enabled() {
+            if let Some(user) = user {
+                let bucket = users
+                    .entry(user.to_owned())
+                    .or_insert_with(|| Bucket::full(&limits.user, now));
+                selected.push((RateLimitScope::User, &limits.user, bucket));
+            }
+        }
+        if limits.channel.is_enabled() {
+            let bucket = channels
+                .entry(channel.to_owned())
+                .or_insert_with(|| Bucket::full(&limits.channel, now));
+            selected.push((RateLimitScope::Channel, &limits.channel, bucket));
+        }
+        if limits.global.is_enabled() {
+            let bucket = global.get_or_insert_with(|| Bucket::full(&limits.global, now));
+            bucket.refill(&limits.global, now);
+            selected.push((RateLimitScope::Global, &limits.global, bucket));
+        }
+
+        let limited = selected
+            .iter()
+            .filter_map(|(scope, limit, bucket)| {
+                bucket.wait(limit).map(|retry_after| RateLimited {
+                    scope: *scope,
+                    retry_after,
+                })
+            })
+            .max_by_key(|limited| limited.retry_after);
+        match limited {
+            Some(limited) => Err(limited),
+            None => {
+                for (_, _, bucket) in selected {
+                    bucket.tokens -= 1.0;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(user: u32, channel: u32, global: u32) -> RateLimitsConfig {
+        let limit = |burst| RateLimitConfig {
+            burst,
+            per_minute: if burst == 0 { 0 } else { 6 },
+        };
+        RateLimitsConfig {
+            user: limit(user),
+            channel: limit(channel),
+            global: limit(global),
+        }
+    }
+
+    #[test]
+    fn test_user_limit() {
+        let limiter = RateLimiter::new();
+        let limits = limits(2, 0, 0);
+        let now = Instant::now();
+        assert!(limiter.check_at(&limits, Some("U1"), "C1", now).is_ok());
+        assert!(limiter.check_at(&limits, Some("U1"), "C1", now).is_ok());
+        let limited = limiter
+            .check_at(&limits, Some("U1"), "C1", now)
+            .unwrap_err();
+        assert_eq!(limited.scope, RateLimitScope::User);
+        assert_eq!(limited.retry_after, Duration::from_secs(10));
+        assert_eq!(
+            limited.message(),
+            "You are running code too often, please retry in 10 seconds."
+        );
+        assert!(limiter.check_at(&limits, Some("U2"), "C1", now).is_ok());
+        let later = now + Duration::from_secs(10);
+        assert!(limiter.check_at(&limits, Some("U1"), "C1", later).is_ok());
+    }
+
+    #[test]
+    fn test_limits_are_checked_together() {
+        let limiter = RateLimiter::new();
+        let limits = limits(1, 2, 0);
+        let now = Instant::now();
+        assert!(limiter.check_at(&limits, Some("U1"), "C1", now).is_ok());
+        // the user is limited, so the channel keeps its token for U2
+        assert!(limiter.check_at(&limits, Some("U1"), "C1", now).is_err());
+        assert!(limiter.check_at(&limits, Some("U2"), "C1", now).is_ok());
+        let limited = limiter
+            .check_at(&limits, Some("U3"), "C1", now)
+            .unwrap_err();
+        assert_eq!(limited.scope, RateLimitScope::Channel);
+        assert!(limiter.check_at(&limits, None, "C2", now).is_ok());
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let limiter = RateLimiter::new();
+        let limits = limits(0, 0, 1);
+        let now = Instant::now();
+        assert!(limiter.check_at(&limits, Some("U1"), "C1", now).is_ok());
+        let limited = limiter
+            .check_at(&limits, Some("U2"), "C2", now)
+            .unwrap_err();
+        assert_eq!(limited.scope, RateLimitScope::Global);
+    }
+}
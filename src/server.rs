@@ -0,0 +1,260 @@
+use crate::bot::{on_command, on_interaction, on_message};
+use crate::config::{Config, HttpConfig};
+use crate::error::RustyBotError;
+use crate::state::BotState;
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use slack_morphism::prelude::*;
+use slack_morphism::signature_verifier::SlackEventSignatureVerifier;
+use slack_morphism_hyper::*;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info};
+
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+type ListenerEnvironment =
+    Arc<SlackClientEventsListenerEnvironment<SlackClientHyperHttpsConnector>>;
+
+/// Serves the Slack requests (events, interactions and slash commands) in HTTP mode, with
+/// the same handlers as Socket Mode, until `shutdown` resolves. Requests must be signed
+/// with the app signing secret.
+pub async fn serve_http<S: Future<Output = ()>>(
+    environment: ListenerEnvironment,
+    config: &Config,
+    shutdown: S,
+) -> Result<(), RustyBotError> {
+    let address: SocketAddr = config.http.address.parse().map_err(|_| {
+        RustyBotError::InvalidConfig(format!(
+            "http.address `{}` should be an ip:port address",
+            config.http.address
+        ))
+    })?;
+    let state = BotState::from_user_state(&environment.user_state)?;
+    let http = Arc::new(config.http.clone());
+    let secret = config.slack.signing_secret.clone();
+
+    let make_service = make_service_fn(move |_| {
+        let environment = environment.clone();
+        let http = http.clone();
+        let verifier = Arc::new(SlackEventSignatureVerifier::new(&secret));
+        let listener = SlackClientEventsHyperListener::new(environment.clone());
+        let slack_routes = chain_service_routes_fn(
+            listener.interaction_events_service_fn(
+                Arc::new(
+                    SlackInteractionEventsListenerConfig::new(secret.clone())
+                        .with_events_path(http.interactions_path.clone()),
+                ),
+                on_interaction,
+            ),
+            chain_service_routes_fn(
+                listener.command_events_service_fn(
+                    Arc::new(
+                        SlackCommandEventsListenerConfig::new(secret.clone())
+                            .with_events_path(http.commands_path.clone()),
+                    ),
+                    on_command,
+                ),
+                not_found,
+            ),
+        );
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                route(
+                    req,
+                    environment.clone(),
+                    http.clone(),
+                    verifier.clone(),
+                    slack_routes.clone(),
+                )
+            }))
+        }
+    });
+
+    let server = bind(&address)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown);
+    info!("Listening to Slack requests on {}", address);
+    // in HTTP mode the bot is reachable by Slack as soon as it listens
+    state.metrics.set_slack_connected(true);
+    server
+        .await
+        .map_err(|e| RustyBotError::InternalServerError(anyhow::anyhow!(e)))
+}
+
+/// Serves `/healthz` (the bot is running), `/readyz` (it is connected to Slack and a
+/// playground endpoint is available) and `/metrics` in the Prometheus text format
+pub async fn serve_monitoring(state: BotState, address: &str) -> Result<(), RustyBotError> {
+    let address: SocketAddr = address.parse().map_err(|_| {
+        RustyBotError::InvalidConfig(format!(
+            "monitoring.address `{}` should be an ip:port address",
+            address
+        ))
+    })?;
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { monitoring_route(req, &state) }
+            }))
+        }
+    });
+    let server = bind(&address)?.serve(make_service);
+    info!("Serving health checks and metrics on {}", address);
+    server
+        .await
+        .map_err(|e| RustyBotError::InternalServerError(anyhow::anyhow!(e)))
+}
+
+fn monitoring_route(req: Request<Body>, state: &BotState) -> Result<Response<Body>, BoxedError> {
+    if req.method() != Method::GET {
+        return status(StatusCode::NOT_FOUND);
+    }
+    match req.uri().path() {
+        "/healthz" => Ok(Response::new(Body::from("ok"))),
+        "/readyz" if state.is_ready() => Ok(Response::new(Body::from("ready"))),
+        "/readyz" => Ok(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from("not ready"))?),
+        "/metrics" => Ok(Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(state.render_metrics()))?),
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+fn bind(address: &SocketAddr) -> Result<hyper::server::Builder<AddrIncoming>, RustyBotError> {
+    Server::try_bind(address).map_err(|e| {
+        RustyBotError::InternalServerError(anyhow::anyhow!("can't listen on {}: {}", address, e))
+    })
+}
+
+/// Rejects the requests that are not for Slack paths or whose timestamp is too old, then
+/// handles the events and passes the interactions and commands to `slack_routes`
+async fn route<R, F>(
+    req: Request<Body>,
+    environment: ListenerEnvironment,
+    http: Arc<HttpConfig>,
+    verifier: Arc<SlackEventSignatureVerifier>,
+    slack_routes: R,
+) -> Result<Response<Body>, BoxedError>
+where
+    R: Fn(Request<Body>) -> F,
+    F: Future<Output = Result<Response<Body>, BoxedError>>,
+{
+    let path = req.uri().path();
+    let is_slack_path =
+        path == http.events_path || path == http.interactions_path || path == http.commands_path;
+    if req.method() != Method::POST || !is_slack_path {
+        return not_found(req).await;
+    }
+    let timestamp = req
+        .headers()
+        .get(SlackEventSignatureVerifier::SLACK_SIGNED_TIMESTAMP)
+        .and_then(|value| value.to_str().ok());
+    if !is_recent(timestamp, http.max_request_age_secs, unix_now()) {
+        debug!(
+            "Rejecting request to {} with timestamp {:?}",
+            path, timestamp
+        );
+        return status(StatusCode::UNAUTHORIZED);
+    }
+    if path == http.events_path {
+        handle_events(req, environment, &verifier).await
+    } else {
+        slack_routes(req).await
+    }
+}
+
+/// Answers the URL verification challenge and passes the events to the message handler
+async fn handle_events(
+    req: Request<Body>,
+    environment: ListenerEnvironment,
+    verifier: &SlackEventSignatureVerifier,
+) -> Result<Response<Body>, BoxedError> {
+    let (parts, body) = req.into_parts();
+    let body = String::from_utf8(hyper::body::to_bytes(body).await?.to_vec())?;
+    let header = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let signature = header(SlackEventSignatureVerifier::SLACK_SIGNED_HASH_HEADER);
+    let timestamp = header(SlackEventSignatureVerifier::SLACK_SIGNED_TIMESTAMP);
+    let verified = match (signature, timestamp) {
+        (Some(signature), Some(timestamp)) => verifier.verify(signature, &body, timestamp),
+        _ => return status(StatusCode::UNAUTHORIZED),
+    };
+    if let Err(e) = verified {
+        debug!("Rejecting event with an invalid signature: {}", e);
+        return status(StatusCode::UNAUTHORIZED);
+    }
+    match serde_json::from_str::<SlackPushEvent>(&body) {
+        Ok(SlackPushEvent::UrlVerification(verification)) => {
+            Ok(Response::new(Body::from(verification.challenge)))
+        }
+        Ok(SlackPushEvent::EventCallback(event)) => {
+            on_message(
+                event,
+                environment.client.clone(),
+                environment.user_state.clone(),
+            )
+            .await?;
+            status(StatusCode::OK)
+        }
+        Ok(SlackPushEvent::AppRateLimited(event)) => {
+            error!(
+                "Error: Slack is rate limiting the events sent to the bot: {:?}",
+                event
+            );
+            status(StatusCode::OK)
+        }
+        Err(e) => {
+            error!("Error: {}\n when reading event body {}", e, body);
+            status(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+async fn not_found(_req: Request<Body>) -> Result<Response<Body>, BoxedError> {
+    status(StatusCode::NOT_FOUND)
+}
+
+fn status(code: StatusCode) -> Result<Response<Body>, BoxedError> {
+    Ok(Response::builder().status(code).body(Body::empty())?)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether the request timestamp (in seconds) is at most `max_age_secs` away from `now`
+fn is_recent(timestamp: Option<&str>, max_age_secs: u64, now: u64) -> bool {
+    match timestamp.and_then(|timestamp| timestamp.parse::<u64>().ok()) {
+        Some(timestamp) => timestamp.max(now) - timestamp.min(now) <= max_age_secs,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_recent() {
+        assert!(is_recent(Some("1000"), 300, 1200));
+        assert!(is_recent(Some("1200"), 300, 1000));
+        assert!(!is_recent(Some("1000"), 300, 1301));
+        assert!(!is_recent(Some("soon"), 300, 1000));
+        assert!(!is_recent(None, 300, 1000));
+    }
+}
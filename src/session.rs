@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub user: String,
+    pub channel: String,
+}
+
+impl SessionKey {
+    pub fn new(user: &str, channel: &str) -> Self {
+        Self {
+            user: user.to_owned(),
+            channel: channel.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    entries: Vec<String>,
+    last_active: Instant,
+}
+
+/// Statements and items accumulated with `!let`, per user per channel. They are replayed
+/// ahead of every `!eval` of the same user in the same channel, and dropped once they
+/// weren't used for `ttl`.
+#[derive(Debug)]
+pub struct SessionStore {
+    ttl: RwLock<Duration>,
+    sessions: Mutex<HashMap<SessionKey, Session>>,
+}
+
+impl SessionStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: RwLock::new(ttl),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_ttl(&self, ttl: Duration) {
+        *self.ttl.write().expect("session ttl lock poisoned") = ttl;
+    }
+
+    /// Runs `f` on the live session of `key`, dropping every expired session first
+    fn with_session<T>(&self, key: &SessionKey, f: impl FnOnce(&mut Session) -> T) -> T {
+        let mut sessions = self.sessions.lock().expect("session store lock poisoned");
+        let ttl = *self.ttl.read().expect("session ttl lock poisoned");
+        sessions.retain(|_, session| session.last_active.elapsed() < ttl);
+        let session = sessions.entry(key.clone()).or_insert_with(|| Session {
+            entries: Vec::new(),
+            last_active: Instant::now(),
+        });
+        session.last_active = Instant::now();
+        f(session)
+    }
+
+    /// The code to run ahead of a new snippet, empty when there is no session
+    pub fn prelude(&self, key: &SessionKey) -> String {
+        self.with_session(key, |session| {
+            session
+                .entries
+                .iter()
+                .map(|entry| format!("{}\n", entry))
+                .collect()
+        })
+    }
+
+    /// Adds an entry to the session and returns the number of entries in it
+    pub fn push(&self, key: &SessionKey, entry: String) -> usize {
+        self.with_session(key, |session| {
+            session.entries.push(entry);
+            session.entries.len()
+        })
+    }
+
+    pub fn undo(&self, key: &SessionKey) -> Option<String> {
+        self.with_session(key, |session| session.entries.pop())
+    }
+
+    pub fn entries(&self, key: &SessionKey) -> Vec<String> {
+        self.with_session(key, |session| session.entries.clone())
+    }
+
+    pub fn reset(&self, key: &SessionKey) {
+        self.with_session(key, |session| session.entries.clear())
+    }
+
+    /// The session as a complete program that can be pasted into the playground
+    pub fn export(&self, key: &SessionKey) -> String {
+        let body: String = self
+            .entries(key)
+            .iter()
+            .map(|entry| format!("    {}\n", entry.replace('\n', "\n    ")))
+            .collect();
+        format!("fn main() {{\n{}}}", body)
+    }
+}
+
+/// Statements that end with a block and need no `;`: items and block expressions
+const BLOCK_STATEMENTS: &[&str] = &[
+    "fn ",
+    "pub ",
+    "struct ",
+    "enum ",
+    "union ",
+    "impl",
+    "trait ",
+    "mod ",
+    "macro_rules!",
+    "async fn ",
+    "unsafe ",
+    "if ",
+    "match ",
+    "loop",
+    "while ",
+    "for ",
+    "{",
+    "#[",
+];
+
+/// Makes a `!let` snippet safe to replay in front of other code by terminating it
+pub fn normalize_entry(code: &str) -> String {
+    let code = code.trim();
+    if code.ends_with(';') || last_statement(code).trim().is_empty() {
+        code.to_owned()
+    } else {
+        format!("{};", code)
+    }
+}
+
+/// The last top level statement of `code`, empty when it ends with an item or a block: what
+/// follows the last `;` or the last item or block. A `}` ending e.g. a struct literal doesn't
+/// end its `let`.
+fn last_statement(code: &str) -> &str {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut chars = code.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                // skip the string, with its escapes
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if code[i..].starts_with("//") => {
+                while matches!(chars.peek(), Some((_, c)) if *c != '\n') {
+                    chars.next();
+                }
+            }
+            '\'' if matches!(code.get(i + 2..), Some(rest) if rest.starts_with('\'')) => {
+                chars.next();
+                chars.next();
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '}' => {
+                depth -= 1;
+                let statement = code[start..].trim_start();
+                if depth == 0
+                    && BLOCK_STATEMENTS
+                        .iter()
+                        .any(|block| statement.starts_with(block))
+                {
+                    start = i + 1;
+                }
+            }
+            ';' if depth == 0 => start = i + 1,
+            _ => {}
+        }
+    }
+    &code[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_lifecycle() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let key = SessionKey::new("U1", "C1");
+        let other_key = SessionKey::new("U2", "C1");
+
+        assert_eq!(store.prelude(&key), "");
+        assert_eq!(store.push(&key, "let x = 5;".to_owned()), 1);
+        assert_eq!(store.push(&key, "let y = x * 2;".to_owned()), 2);
+        assert_eq!(store.prelude(&key), "let x = 5;\nlet y = x * 2;\n");
+        assert_eq!(store.prelude(&other_key), "");
+        assert_eq!(
+            store.export(&key),
+            "fn main() {\n    let x = 5;\n    let y = x * 2;\n}"
+        );
+
+        assert_eq!(store.undo(&key), Some("let y = x * 2;".to_owned()));
+        assert_eq!(store.entries(&key), vec!["let x = 5;".to_owned()]);
+        store.reset(&key);
+        assert!(store.entries(&key).is_empty());
+    }
+
+    #[test]
+    fn test_session_expires() {
+        let store = SessionStore::new(Duration::from_millis(0));
+        let key = SessionKey::new("U1", "C1");
+        store.push(&key, "let x = 5;".to_owned());
+        assert!(store.entries(&key).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_entry() {
+        assert_eq!(normalize_entry("let x = 5"), "let x = 5;");
+        assert_eq!(normalize_entry("let x = 5;\n"), "let x = 5;");
+        assert_eq!(normalize_entry("fn f() {}"), "fn f() {}");
+        assert_eq!(
+            normalize_entry("let p = Point { x: 1, y: 2 }"),
+            "let p = Point { x: 1, y: 2 };"
+        );
+        assert_eq!(
+            normalize_entry("let x = if c { 1 } else { 2 }"),
+            "let x = if c { 1 } else { 2 };"
+        );
+        assert_eq!(
+            normalize_entry("struct P { x: i32 }\nlet p = P { x: 1 }"),
+            "struct P { x: i32 }\nlet p = P { x: 1 };"
+        );
+        assert_eq!(
+            normalize_entry("let s = \"}\";\nfor i in 0..3 { v.push(i) }"),
+            "let s = \"}\";\nfor i in 0..3 { v.push(i) }"
+        );
+        assert_eq!(normalize_entry("v = vec!['}']"), "v = vec!['}'];");
+        assert_eq!(normalize_entry("let c = 'é'"), "let c = 'é';");
+    }
+}
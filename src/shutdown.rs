@@ -0,0 +1,172 @@
+use slack_morphism::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::timeout;
+use tracing::{error, info};
+
+/// Keeps track of the events being handled and of the placeholder messages waiting for
+/// their result, so the bot can stop without leaving users without a reply
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    stopping: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    placeholders: Mutex<HashMap<u64, (SlackChannelId, SlackTs)>>,
+    next_id: AtomicU64,
+}
+
+/// An event being handled, until it is dropped
+#[derive(Debug)]
+pub struct InFlight(Arc<Shutdown>);
+
+/// A placeholder message waiting for its result, until it is dropped
+#[derive(Debug)]
+pub struct PendingPlaceholder {
+    shutdown: Arc<Shutdown>,
+    id: u64,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Starts handling an event, `None` once the bot is stopping
+    pub fn track(self: &Arc<Self>) -> Option<InFlight> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self.clone());
+        if self.is_stopping() {
+            None
+        } else {
+            Some(in_flight)
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn add_placeholder(
+        self: &Arc<Self>,
+        channel: SlackChannelId,
+        ts: SlackTs,
+    ) -> PendingPlaceholder {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.placeholders
+            .lock()
+            .expect("shutdown lock poisoned")
+            .insert(id, (channel, ts));
+        PendingPlaceholder {
+            shutdown: self.clone(),
+            id,
+        }
+    }
+
+    pub fn pending_placeholders(&self) -> Vec<(SlackChannelId, SlackTs)> {
+        self.placeholders
+            .lock()
+            .expect("shutdown lock poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Stops accepting events and waits up to `deadline` for the ones in flight. Returns
+    /// `false` when some of them are still running.
+    pub async fn drain(&self, deadline: Duration) -> bool {
+        self.stopping.store(true, Ordering::SeqCst);
+        let idle = async {
+            loop {
+                let notified = self.idle.notified();
+                if self.in_flight() == 0 {
+                    break;
+                }
+                notified.await;
+            }
+        };
+        timeout(deadline, idle).await.is_ok()
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl Drop for PendingPlaceholder {
+    fn drop(&mut self) {
+        self.shutdown
+            .placeholders
+            .lock()
+            .expect("shutdown lock poisoned")
+            .remove(&self.id);
+    }
+}
+
+/// Resolves when the process receives SIGTERM or SIGINT
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Error: {}\n when listening to SIGTERM, only SIGINT stops the bot",
+                    e
+                );
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+    info!("Shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let shutdown = Arc::new(Shutdown::new());
+        let first = shutdown.track().unwrap();
+        let second = shutdown.track().unwrap();
+        let placeholder = shutdown.add_placeholder("C1".into(), "1.0".into());
+        assert_eq!(shutdown.pending_placeholders().len(), 1);
+
+        assert!(!runtime.block_on(shutdown.drain(Duration::from_millis(10))));
+        assert!(shutdown.track().is_none());
+        assert_eq!(shutdown.in_flight(), 2);
+
+        drop(first);
+        drop(placeholder);
+        assert!(shutdown.pending_placeholders().is_empty());
+        runtime.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(second);
+        });
+        assert!(runtime.block_on(shutdown.drain(Duration::from_secs(5))));
+    }
+}
@@ -1,18 +1,86 @@
+use crate::books::{Book, Section};
+use crate::commands::{commands_text, HelpTopic, COMMANDS};
+use crate::crates_io::CrateInfo;
+use crate::docs::{DocLookup, Stability};
+use crate::playground::{ChannelVersions, CrateInformation, ToolVersion, VersionsResponse};
 use slack_morphism::prelude::*;
 
+/// Cuts `output` down to its first `max_chars` characters, noting that it was truncated
+pub fn truncate_output(output: &str, max_chars: usize) -> String {
+    match output.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}\n... (truncated)", &output[..index]),
+        None => output.to_owned(),
+    }
+}
+
+/// Action id of the button cancelling a queued or running snippet, its value is the job id
+pub const CANCEL_ACTION_ID: &str = "cancel-execution";
+
+/// The message shown while a snippet waits in the queue and runs
+#[derive(Debug, Clone)]
+pub struct PlaceholderTemplate {
+    pub text: String,
+    pub job_id: u64,
+}
+
+impl PlaceholderTemplate {
+    pub fn new(text: String, job_id: u64) -> Self {
+        Self { text, job_id }
+    }
+}
+
+impl SlackMessageTemplate for PlaceholderTemplate {
+    fn render_template(&self) -> SlackMessageContent {
+        SlackMessageContent::new()
+            .with_text(self.text.clone())
+            .with_blocks(slack_blocks![
+                some_into(SlackSectionBlock::new().with_text(md!("{}", self.text))),
+                some_into(SlackActionsBlock::new(slack_blocks![some_into(
+                    SlackBlockButtonElement::new(
+                        SlackActionId(CANCEL_ACTION_ID.to_owned()),
+                        pt!("Cancel")
+                    )
+                    .with_value(self.job_id.to_string())
+                )]))
+            ])
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CodeReplyTemplate<'a> {
-    pub share_link: &'a str,
+    pub share_link: Option<&'a str>,
     pub stdout: String,
     pub stderr: String,
+    pub result: Option<String>,
+    pub cached: bool,
+    /// The share link was wanted but couldn't be created
+    pub share_failed: bool,
 }
 
 impl<'a> CodeReplyTemplate<'a> {
-    pub fn new(share_link: &'a str, stdout: String, stderr: String) -> Self {
+    pub fn new(share_link: Option<&'a str>, stdout: String, stderr: String) -> Self {
         Self {
             share_link,
             stdout,
             stderr,
+            result: None,
+            cached: false,
+            share_failed: false,
+        }
+    }
+
+    pub fn with_result(self, result: Option<String>) -> Self {
+        Self { result, ..self }
+    }
+
+    pub fn with_cached(self, cached: bool) -> Self {
+        Self { cached, ..self }
+    }
+
+    pub fn with_share_failed(self, share_failed: bool) -> Self {
+        Self {
+            share_failed,
+            ..self
         }
     }
 }
@@ -25,13 +93,30 @@ impl<'a> SlackMessageTemplate for CodeReplyTemplate<'a> {
                 some_into(SlackHeaderBlock::new(SlackBlockText::Plain(
                     SlackBlockPlainText::new("Rust Playground".to_owned())
                 ))),
-                some_into(SlackActionsBlock::new(slack_blocks![some_into(
+                optionally_into(self.share_link.is_some() => SlackActionsBlock::new(slack_blocks![some_into(
                     SlackBlockButtonElement::new(
                         SlackActionId("button-action".to_owned()),
                         pt!("Code")
                     )
-                    .with_url(self.share_link.to_owned())
+                    .with_url(self.share_link.unwrap_or_default().to_owned())
                 )])),
+                optionally_into(self.share_failed => SlackContextBlock::new(vec![
+                    SlackContextBlockElement::Plain(SlackBlockPlainText::new(
+                        "Share link unavailable".to_owned()
+                    ))
+                ])),
+                optionally_into(self.cached => SlackContextBlock::new(vec![
+                    SlackContextBlockElement::Plain(SlackBlockPlainText::new(
+                        "Cached result, this code ran recently with the same settings".to_owned()
+                    ))
+                ])),
+                optionally_into(self.result.is_some() => SlackContextBlock::new(vec![
+                    SlackContextBlockElement::Plain(SlackBlockPlainText::new("Result".to_owned()))
+                ])),
+                optionally_into(self.result.is_some() => SlackSectionBlock::new().with_text(
+                    md!("```{}```", self.result.as_deref().unwrap_or_default())
+                )),
+                optionally_into(self.result.is_some() => SlackDividerBlock::new()),
                 some_into(SlackContextBlock::new(vec![
                     SlackContextBlockElement::Plain(SlackBlockPlainText::new("Stdout".to_owned()))
                 ])),
@@ -44,3 +129,397 @@ impl<'a> SlackMessageTemplate for CodeReplyTemplate<'a> {
             ])
     }
 }
+
+/// Crates per section block, keeps each section under Slack's text limit
+const CRATES_PER_SECTION: usize = 40;
+
+#[derive(Debug, Clone)]
+pub struct CratesReplyTemplate {
+    pub crates: Vec<CrateInformation>,
+    pub filter: Option<String>,
+}
+
+impl CratesReplyTemplate {
+    /// Keeps the crates whose name contains `filter`, case insensitively
+    pub fn new(crates: Vec<CrateInformation>, filter: Option<String>) -> Self {
+        let crates = match filter {
+            Some(ref filter) => {
+                let filter = filter.to_lowercase();
+                crates
+                    .into_iter()
+                    .filter(|krate| krate.name.to_lowercase().contains(&filter))
+                    .collect()
+            }
+            None => crates,
+        };
+        Self { crates, filter }
+    }
+
+    fn summary(&self) -> String {
+        match (self.crates.len(), &self.filter) {
+            (0, Some(filter)) => format!("No crates match `{}`", filter),
+            (count, Some(filter)) => format!("{} crates match `{}`", count, filter),
+            (count, None) => format!(
+                "{} crates are available, use `!crates <name>` to search",
+                count
+            ),
+        }
+    }
+}
+
+impl SlackMessageTemplate for CratesReplyTemplate {
+    fn render_template(&self) -> SlackMessageContent {
+        let mut blocks: Vec<SlackBlock> = slack_blocks![
+            some_into(SlackHeaderBlock::new(SlackBlockText::Plain(
+                SlackBlockPlainText::new("Playground crates".to_owned())
+            ))),
+            some_into(SlackContextBlock::new(vec![
+                SlackContextBlockElement::MarkDown(SlackBlockMarkDownText::new(self.summary()))
+            ]))
+        ];
+        blocks.extend(self.crates.chunks(CRATES_PER_SECTION).map(|chunk| {
+            let lines: Vec<String> = chunk
+                .iter()
+                .map(|krate| format!("`{}` {}", krate.name, krate.version))
+                .collect();
+            SlackSectionBlock::new()
+                .with_text(md!(lines.join("\n")))
+                .into()
+        }));
+        SlackMessageContent::new()
+            .with_text(self.summary())
+            .with_blocks(blocks)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VersionsReplyTemplate {
+    pub versions: VersionsResponse,
+}
+
+impl VersionsReplyTemplate {
+    pub fn new(versions: VersionsResponse) -> Self {
+        Self { versions }
+    }
+
+    fn channel_section(name: &str, channel: &ChannelVersions) -> SlackSectionBlock {
+        let tool = |tool_name: &str, tool: &Option<ToolVersion>| match tool {
+            Some(tool) => md!("*{}*\n{}", tool_name, tool.version),
+            None => md!("*{}*\nnot installed", tool_name),
+        };
+        SlackSectionBlock::new()
+            .with_text(md!(
+                "*{}*: rustc {} ({} {})",
+                name,
+                channel.rustc.version,
+                channel.rustc.hash.chars().take(9).collect::<String>(),
+                channel.rustc.date
+            ))
+            .with_fields(vec![
+                tool("rustfmt", &channel.rustfmt),
+                tool("clippy", &channel.clippy),
+                tool("miri", &channel.miri),
+            ])
+    }
+}
+
+impl SlackMessageTemplate for VersionsReplyTemplate {
+    fn render_template(&self) -> SlackMessageContent {
+        SlackMessageContent::new()
+            .with_text(format!(
+                "Playground toolchains: stable {}, beta {}, nightly {}",
+                self.versions.stable.rustc.version,
+                self.versions.beta.rustc.version,
+                self.versions.nightly.rustc.version
+            ))
+            .with_blocks(slack_blocks![
+                some_into(SlackHeaderBlock::new(SlackBlockText::Plain(
+                    SlackBlockPlainText::new("Playground toolchains".to_owned())
+                ))),
+                some_into(Self::channel_section("Stable", &self.versions.stable)),
+                some_into(SlackDividerBlock::new()),
+                some_into(Self::channel_section("Beta", &self.versions.beta)),
+                some_into(SlackDividerBlock::new()),
+                some_into(Self::channel_section("Nightly", &self.versions.nightly))
+            ])
+    }
+}
+
+/// The answer to `!help`, generated from the command registry
+#[derive(Debug, Clone)]
+pub struct HelpReplyTemplate {
+    pub topic: HelpTopic,
+}
+
+impl HelpReplyTemplate {
+    pub fn new(topic: HelpTopic) -> Self {
+        Self { topic }
+    }
+}
+
+impl SlackMessageTemplate for HelpReplyTemplate {
+    fn render_template(&self) -> SlackMessageContent {
+        match self.topic {
+            HelpTopic::Link(link) => SlackMessageContent::new().with_text(link.to_owned()),
+            HelpTopic::Commands => {
+                let lines: Vec<String> = COMMANDS
+                    .iter()
+                    .map(|command| format!("`{}` - {}", command.usage(), command.description))
+                    .collect();
+                SlackMessageContent::new()
+                    .with_text(commands_text())
+                    .with_blocks(slack_blocks![
+                        some_into(SlackHeaderBlock::new(SlackBlockText::Plain(
+                            SlackBlockPlainText::new("Available commands".to_owned())
+                        ))),
+                        some_into(SlackSectionBlock::new().with_text(md!(lines.join("\n")))),
+                        some_into(SlackContextBlock::new(vec![
+                            SlackContextBlockElement::MarkDown(SlackBlockMarkDownText::new(
+                                "Use `!help <command>` for examples. _Yours truly, Ferris_"
+                                    .to_owned()
+                            ))
+                        ]))
+                    ])
+            }
+            HelpTopic::Command(command) => {
+                let aliases: Vec<String> = command
+                    .aliases
+                    .iter()
+                    .map(|alias| format!("`!{}`", alias))
+                    .collect();
+                let examples: Vec<String> = command
+                    .examples
+                    .iter()
+                    .map(|example| match example.split_once('\n') {
+                        Some((line, code)) => format!("`{}`\n{}", line, code),
+                        None => format!("`{}`", example),
+                    })
+                    .collect();
+                SlackMessageContent::new()
+                    .with_text(format!("{} - {}", command.usage(), command.description))
+                    .with_blocks(slack_blocks![
+                        some_into(SlackHeaderBlock::new(SlackBlockText::Plain(
+                            SlackBlockPlainText::new(format!("!{}", command.name))
+                        ))),
+                        some_into(SlackSectionBlock::new().with_text(md!(
+                            "`{}`\n{}",
+                            command.usage(),
+                            command.description
+                        ))),
+                        optionally_into(!aliases.is_empty() => SlackContextBlock::new(vec![
+                            SlackContextBlockElement::MarkDown(SlackBlockMarkDownText::new(
+                                format!("Also answers to {}", aliases.join(", "))
+                            ))
+                        ])),
+                        optionally_into(!examples.is_empty() => SlackSectionBlock::new()
+                            .with_text(md!("*Examples*\n{}", examples.join("\n\n"))))
+                    ])
+            }
+        }
+    }
+}
+
+/// The answer to `!doc`
+#[derive(Debug, Clone)]
+pub struct DocReplyTemplate<'a> {
+    pub query: &'a str,
+    pub lookup: DocLookup<'a>,
+}
+
+impl<'a> DocReplyTemplate<'a> {
+    pub fn new(query: &'a str, lookup: DocLookup<'a>) -> Self {
+        Self { query, lookup }
+    }
+}
+
+impl<'a> SlackMessageTemplate for DocReplyTemplate<'a> {
+    fn render_template(&self) -> SlackMessageContent {
+        let item = match self.lookup {
+            DocLookup::Found(item) => item,
+            DocLookup::NotFound(ref suggestions) if suggestions.is_empty() => {
+                return SlackMessageContent::new().with_text(format!(
+                    "No std, core or alloc item matches `{}`.",
+                    self.query
+                ))
+            }
+            DocLookup::NotFound(ref suggestions) => {
+                let paths: Vec<String> = suggestions
+                    .iter()
+                    .map(|item| format!("`{}`", item.path))
+                    .collect();
+                return SlackMessageContent::new().with_text(format!(
+                    "No std, core or alloc item matches `{}`, did you mean {}?",
+                    self.query,
+                    paths.join(", ")
+                ));
+            }
+        };
+        let stability = match item.stability {
+            Stability::Stable(ref since) => format!("Stable since Rust {}", since),
+            Stability::Unstable(ref feature) => {
+                format!("Nightly only, behind the `{}` feature", feature)
+            }
+            Stability::Deprecated => "Deprecated".to_owned(),
+            Stability::Unknown => "Stability unknown".to_owned(),
+        };
+        SlackMessageContent::new()
+            .with_text(format!("{}: {}", item.path, item.summary))
+            .with_blocks(slack_blocks![
+                some_into(SlackSectionBlock::new().with_text(md!(
+                    "*<{}|{}>* _{}_",
+                    item.url(),
+                    item.path,
+                    item.kind
+                ))),
+                optionally_into(!item.signature.is_empty() => SlackSectionBlock::new()
+                    .with_text(md!("```{}```", item.signature))),
+                optionally_into(!item.summary.is_empty() => SlackSectionBlock::new()
+                    .with_text(md!(item.summary.clone()))),
+                some_into(SlackContextBlock::new(vec![
+                    SlackContextBlockElement::Plain(SlackBlockPlainText::new(stability))
+                ]))
+            ])
+    }
+}
+
+/// A crate looked up on crates.io
+#[derive(Debug, Clone)]
+pub struct CrateReplyTemplate<'a> {
+    pub krate: &'a CrateInfo,
+    /// The crates.io base URL, for the link to the crate's page
+    pub crates_io_url: &'a str,
+}
+
+impl<'a> CrateReplyTemplate<'a> {
+    pub fn new(krate: &'a CrateInfo, crates_io_url: &'a str) -> Self {
+        Self {
+            krate,
+            crates_io_url,
+        }
+    }
+}
+
+impl<'a> SlackMessageTemplate for CrateReplyTemplate<'a> {
+    fn render_template(&self) -> SlackMessageContent {
+        let krate = self.krate;
+        let description = krate
+            .description
+            .as_deref()
+            .map(str::trim)
+            .unwrap_or_default();
+        let mut links = vec![
+            format!("<{}/crates/{}|crates.io>", self.crates_io_url, krate.name),
+            format!("<{}|docs.rs>", krate.docs_url()),
+        ];
+        if let Some(ref repository) = krate.repository {
+            links.push(format!("<{}|repository>", repository));
+        }
+        if let Some(ref homepage) = krate.homepage {
+            if krate.repository.as_ref() != Some(homepage) {
+                links.push(format!("<{}|homepage>", homepage));
+            }
+        }
+        let field = |name: &str, value: String| md!("*{}*\n{}", name, value);
+        let mut fields = vec![
+            field("Latest version", krate.latest_version().to_owned()),
+            field("Downloads", format_count(krate.downloads)),
+        ];
+        if let Some(recent_downloads) = krate.recent_downloads {
+            fields.push(field("Recent downloads", format_count(recent_downloads)));
+        }
+        // the date of an RFC 3339 timestamp
+        fields.push(field(
+            "Last updated",
+            krate.updated_at.chars().take(10).collect(),
+        ));
+        SlackMessageContent::new()
+            .with_text(format!("{}: {}", krate.name, krate.cargo_toml_line()))
+            .with_blocks(slack_blocks![
+                some_into(SlackHeaderBlock::new(SlackBlockText::Plain(
+                    SlackBlockPlainText::new(krate.name.clone())
+                ))),
+                optionally_into(!description.is_empty() => SlackSectionBlock::new()
+                    .with_text(SlackBlockText::Plain(SlackBlockPlainText::new(description.to_owned())))),
+                some_into(SlackSectionBlock::new().with_fields(fields)),
+                some_into(SlackSectionBlock::new()
+                    .with_text(md!("```{}```", krate.cargo_toml_line()))),
+                some_into(SlackContextBlock::new(vec![
+                    SlackContextBlockElement::MarkDown(SlackBlockMarkDownText::new(links.join(" · ")))
+                ]))
+            ])
+    }
+}
+
+/// `count` with thousands separators, e.g. `1,234,567`
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let first_group = match digits.len() % 3 {
+        0 => 3,
+        len => len,
+    };
+    let (first, rest) = digits.split_at(first_group);
+    let mut formatted = first.to_owned();
+    for (i, digit) in rest.chars().enumerate() {
+        if i % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// The sections of a book matching a search
+#[derive(Debug, Clone)]
+pub struct BookReplyTemplate<'a> {
+    pub book: &'a Book,
+    pub query: &'a str,
+    pub sections: Vec<&'a Section>,
+}
+
+impl<'a> BookReplyTemplate<'a> {
+    pub fn new(book: &'a Book, query: &'a str, sections: Vec<&'a Section>) -> Self {
+        Self {
+            book,
+            query,
+            sections,
+        }
+    }
+}
+
+impl<'a> SlackMessageTemplate for BookReplyTemplate<'a> {
+    fn render_template(&self) -> SlackMessageContent {
+        if self.sections.is_empty() {
+            return SlackMessageContent::new().with_text(format!(
+                "Nothing in <{}|{}> matches `{}`.",
+                self.book.url, self.book.title, self.query
+            ));
+        }
+        let lines: Vec<String> = self
+            .sections
+            .iter()
+            .map(|section| {
+                if section.is_chapter() || section.heading == section.chapter {
+                    format!("• <{}|{}>", section.url(), section.heading)
+                } else {
+                    format!(
+                        "• <{}|{}> _in {}_",
+                        section.url(),
+                        section.heading,
+                        section.chapter
+                    )
+                }
+            })
+            .collect();
+        SlackMessageContent::new()
+            .with_text(format!("{}: {}", self.book.title, self.sections[0].url()))
+            .with_blocks(slack_blocks![
+                some_into(SlackContextBlock::new(vec![
+                    SlackContextBlockElement::MarkDown(SlackBlockMarkDownText::new(format!(
+                        "<{}|{}> sections matching `{}`",
+                        self.book.url, self.book.title, self.query
+                    )))
+                ])),
+                some_into(SlackSectionBlock::new().with_text(md!(lines.join("\n"))))
+            ])
+    }
+}
@@ -0,0 +1,209 @@
+use crate::bot::{on_command, on_interaction, on_message};
+use crate::config::Config;
+use crate::error::RustyBotError;
+use crate::state::BotState;
+use async_trait::async_trait;
+use serde::Serialize;
+use slack_morphism::clients_manager::{
+    SlackSocketModeClientsManager, SlackSocketModeClientsManagerFactory,
+};
+use slack_morphism::prelude::*;
+use slack_morphism_hyper::*;
+use slack_morphism_models::socket_mode::*;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use tracing::{debug, error};
+
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+type ListenerEnvironment =
+    Arc<SlackClientEventsListenerEnvironment<SlackClientHyperHttpsConnector>>;
+type ClientsManager = Arc<dyn SlackSocketModeClientsManager + Send + Sync>;
+
+/// Receives the Slack events, interactions and slash commands over Socket Mode, with the
+/// same handlers as HTTP mode, until `shutdown` resolves. The bot is connected to Slack
+/// while at least one of the connections Slack said hello on is open.
+pub async fn serve_socket_mode<S: Future<Output = ()>>(
+    environment: ListenerEnvironment,
+    config: &Config,
+    shutdown: S,
+) -> Result<(), RustyBotError> {
+    let state = BotState::from_user_state(&environment.user_state)?;
+    let clients_manager: ClientsManager = environment
+        .client
+        .http_api
+        .connector
+        .new_clients_manager(environment.clone());
+    let listener = Arc::new(ConnectionListener {
+        clients_manager: Arc::downgrade(&clients_manager),
+        environment,
+        state,
+        connections: Connections::default(),
+    });
+    let app_token = SlackApiToken::new(SlackApiTokenValue(config.slack.app_token.clone()));
+    clients_manager
+        .register_new_token(&SlackClientSocketModeConfig::new(), app_token, listener)
+        .await?;
+    clients_manager.start().await;
+
+    shutdown.await;
+    clients_manager.shutdown().await;
+    Ok(())
+}
+
+/// The Socket Mode connections Slack said hello on and that aren't closed yet
+#[derive(Default)]
+struct Connections {
+    open: Mutex<HashSet<SlackSocketModeWssClientId>>,
+}
+
+impl Connections {
+    /// Returns whether a connection is open afterwards
+    fn opened(&self, client_id: &SlackSocketModeWssClientId) -> bool {
+        let mut open = self.open.lock().unwrap();
+        open.insert(client_id.clone());
+        !open.is_empty()
+    }
+
+    /// Returns whether a connection is still open afterwards
+    fn closed(&self, client_id: &SlackSocketModeWssClientId) -> bool {
+        let mut open = self.open.lock().unwrap();
+        open.remove(client_id);
+        !open.is_empty()
+    }
+}
+
+/// Handles the messages of the Socket Mode connections like the listener of slack-morphism
+/// does, and follows them being opened and closed to tell whether the bot is connected
+struct ConnectionListener {
+    clients_manager: Weak<dyn SlackSocketModeClientsManager + Send + Sync>,
+    environment: ListenerEnvironment,
+    state: BotState,
+    connections: Connections,
+}
+
+impl ConnectionListener {
+    /// The connection is closed, it is replaced by a new one
+    async fn reconnect(&self, client_id: &SlackSocketModeWssClientId) {
+        let connected = self.connections.closed(client_id);
+        self.state.metrics.set_slack_connected(connected);
+        if let Some(clients_manager) = self.clients_manager.upgrade() {
+            clients_manager.restart_client(client_id).await;
+        }
+    }
+
+    /// Serializes the acknowledgement, unless handling the envelope failed and the error
+    /// handler wants Slack to send it again
+    fn acknowledge<A: Serialize>(&self, ack: A, result: Result<(), BoxedError>) -> Option<String> {
+        if let Err(err) = result {
+            if !self.handle_error(err).is_success() {
+                return None;
+            }
+        }
+        match serde_json::to_string(&ack) {
+            Ok(ack) => Some(ack),
+            Err(e) => {
+                error!("Error: {}\n when acknowledging a Socket Mode envelope", e);
+                None
+            }
+        }
+    }
+
+    fn handle_error(&self, err: BoxedError) -> http::StatusCode {
+        (self.environment.error_handler)(
+            err,
+            self.environment.client.clone(),
+            self.environment.user_state.clone(),
+        )
+    }
+}
+
+#[async_trait]
+impl SlackSocketModeClientListener for ConnectionListener {
+    async fn on_message(
+        &self,
+        client_id: &SlackSocketModeWssClientId,
+        message_body: String,
+    ) -> Option<String> {
+        let event = match serde_json::from_str::<SlackSocketModeEvent>(&message_body) {
+            Ok(event) => event,
+            Err(e) => {
+                self.handle_error(Box::new(e));
+                return None;
+            }
+        };
+        let client = self.environment.client.clone();
+        let states = self.environment.user_state.clone();
+        match event {
+            SlackSocketModeEvent::Hello(event) => {
+                // Slack says hello every time a connection is (re)opened
+                debug!(
+                    "Connected to Slack on {}: {:?}",
+                    client_id.to_string(),
+                    event
+                );
+                let connected = self.connections.opened(client_id);
+                self.state.metrics.set_slack_connected(connected);
+                None
+            }
+            SlackSocketModeEvent::Disconnect(event) => {
+                debug!(
+                    "Slack is closing the connection {}: {:?}",
+                    client_id.to_string(),
+                    event
+                );
+                self.reconnect(client_id).await;
+                None
+            }
+            SlackSocketModeEvent::EventsApi(event) => {
+                let ack =
+                    SlackSocketModeEventCommonAcknowledge::new(event.envelope_params.envelope_id);
+                let result = on_message(event.payload, client, states).await;
+                self.acknowledge(ack, result)
+            }
+            SlackSocketModeEvent::Interactive(event) => {
+                let ack =
+                    SlackSocketModeEventCommonAcknowledge::new(event.envelope_params.envelope_id);
+                let result = on_interaction(event.payload, client, states).await;
+                self.acknowledge(ack, result)
+            }
+            SlackSocketModeEvent::SlashCommands(event) => {
+                let ack = SlackSocketModeCommandEventAck::new(
+                    SlackSocketModeEventCommonAcknowledge::new(event.envelope_params.envelope_id),
+                );
+                match on_command(event.payload, client, states).await {
+                    Ok(response) => self.acknowledge(ack.with_payload(response), Ok(())),
+                    Err(err) => self.acknowledge(ack, Err(err)),
+                }
+            }
+        }
+    }
+
+    async fn on_error(&self, err: BoxedError) {
+        self.handle_error(err);
+    }
+
+    async fn on_disconnect(&self, client_id: &SlackSocketModeWssClientId) {
+        debug!("Lost the connection {} to Slack", client_id.to_string());
+        self.reconnect(client_id).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections() {
+        let connections = Connections::default();
+        let first = SlackSocketModeWssClientId::new(0, 0, 0);
+        let second = SlackSocketModeWssClientId::new(1, 1, 0);
+        assert!(connections.opened(&first));
+        assert!(connections.opened(&second));
+        assert!(connections.closed(&first));
+        // a connection can be reported closed more than once
+        assert!(connections.closed(&first));
+        assert!(!connections.closed(&second));
+        assert!(connections.opened(&first.new_reconnected_id()));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::books::BookIndex;
+use crate::cache::{ResultCache, TtlCell};
+use crate::config::{Config, SharedConfig};
+use crate::crates_io::{CrateInfo, CratesIoClient};
+use crate::dedup::SeenEvents;
+use crate::docs::DocIndex;
+use crate::error::RustyBotError;
+use crate::failover::Endpoints;
+use crate::metrics::{Gauges, Metrics};
+use crate::playground::{CrateInformation, PlaygroundAnswer, PlaygroundClient, VersionsResponse};
+use crate::queue::ExecutionQueue;
+use crate::ratelimit::RateLimiter;
+use crate::session::SessionStore;
+use crate::shutdown::Shutdown;
+use slack_morphism::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{debug, error};
+
+/// State shared by the event handlers, registered as the listener's user state
+#[derive(Debug, Clone)]
+pub struct BotState {
+    pub config: Arc<SharedConfig>,
+    pub sessions: Arc<SessionStore>,
+    pub crates: Arc<TtlCell<Vec<CrateInformation>>>,
+    pub versions: Arc<TtlCell<VersionsResponse>>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub queue: Arc<ExecutionQueue>,
+    pub results: Arc<ResultCache<PlaygroundAnswer>>,
+    pub playground: PlaygroundClient,
+    pub crates_io: CratesIoClient,
+    /// The crates looked up with `!crate`
+    pub crate_info: Arc<ResultCache<CrateInfo>>,
+    pub endpoints: Arc<Endpoints>,
+    pub metrics: Arc<Metrics>,
+    pub shutdown: Arc<Shutdown>,
+    pub seen_events: Arc<SeenEvents>,
+    pub docs: Arc<DocIndex>,
+    pub books: Arc<BookIndex>,
+    /// The bot's own user, resolved at startup with `auth.test`
+    pub bot_user_id: Option<SlackUserId>,
+}
+
+impl BotState {
+    pub fn new(config: Config) -> Result<Self, RustyBotError> {
+        let metadata_ttl = Duration::from_secs(config.limits.metadata_ttl_secs);
+        let metrics = Arc::new(Metrics::new());
+        Ok(Self {
+            playground: PlaygroundClient::new(&config.playground)?.with_metrics(metrics.clone()),
+            crates_io: CratesIoClient::new(&config.crates_io)?,
+            crate_info: Arc::new(ResultCache::new()),
+            endpoints: Arc::new(Endpoints::new()),
+            metrics,
+            shutdown: Arc::new(Shutdown::new()),
+            seen_events: Arc::new(SeenEvents::default()),
+            docs: Arc::new(DocIndex::bundled()),
+            books: Arc::new(BookIndex::bundled()),
+            bot_user_id: None,
+            sessions: Arc::new(SessionStore::new(Duration::from_secs(
+                config.limits.session_ttl_secs,
+            ))),
+            crates: Arc::new(TtlCell::new(metadata_ttl)),
+            versions: Arc::new(TtlCell::new(metadata_ttl)),
+            rate_limiter: Arc::new(RateLimiter::new()),
+            queue: Arc::new(ExecutionQueue::new(config.queue.workers)),
+            results: Arc::new(ResultCache::new()),
+            config: Arc::new(SharedConfig::new(config)),
+        })
+    }
+
+    pub fn with_bot_user_id(self, bot_user_id: Option<SlackUserId>) -> Self {
+        Self {
+            bot_user_id,
+            ..self
+        }
+    }
+
+    /// Reloads the configuration and applies the new limits to the running components.
+    /// On error the current configuration stays in place.
+    pub fn reload_config(&self) -> Result<Arc<Config>, RustyBotError> {
+        let config = self.config.reload()?;
+        let metadata_ttl = Duration::from_secs(config.limits.metadata_ttl_secs);
+        self.sessions
+            .set_ttl(Duration::from_secs(config.limits.session_ttl_secs));
+        self.crates.set_ttl(metadata_ttl);
+        self.versions.set_ttl(metadata_ttl);
+        Ok(config)
+    }
+
+    /// Whether the bot can answer: it is connected to Slack and at least one playground
+    /// endpoint is available
+    pub fn is_ready(&self) -> bool {
+        let urls = self.config.current().playground.urls();
+        self.metrics.is_slack_connected()
+            && self
+                .endpoints
+                .metrics(&urls)
+                .iter()
+                .any(|endpoint| !endpoint.open)
+    }
+
+    pub fn render_metrics(&self) -> String {
+        let urls = self.config.current().playground.urls();
+        self.metrics.render(&Gauges {
+            queue_depth: self.queue.len(),
+            endpoints: self.endpoints.metrics(&urls),
+        })
+    }
+
+    pub fn from_user_state(states: &SlackClientEventsUserState) -> Result<Self, RustyBotError> {
+        states
+            .read()
+            .map_err(|e| RustyBotError::InternalServerError(anyhow::anyhow!("{}", e)))?
+            .get_user_state::<BotState>()
+            .cloned()
+            .ok_or_else(|| {
+                RustyBotError::InternalServerError(anyhow::anyhow!(
+                    "BotState is not registered in the listener user state"
+                ))
+            })
+    }
+}
+
+/// Reloads the configuration every time the process receives a SIGHUP
+#[cfg(unix)]
+pub async fn reload_config_on_sighup(state: BotState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!(
+                "Error: {}\n when listening to SIGHUP, config reload on signal is disabled",
+                e
+            );
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        // errors are logged by the reload itself
+        let _ = state.reload_config();
+    }
+}
+
+/// Checks every playground endpoint on `playground.health_check_interval_secs`, so an
+/// endpoint skipped after failures is used again as soon as it answers
+pub async fn check_playground_health(state: BotState) {
+    loop {
+        let config = state.config.current();
+        let interval = config.playground.health_check_interval_secs;
+        if interval == 0 {
+            // checked again later in case a reload enables the checks
+            sleep(Duration::from_secs(60)).await;
+            continue;
+        }
+        for url in config.playground.urls() {
+            let started = Instant::now();
+            match state.playground.fetch_versions(&url).await {
+                Ok(_) => state.endpoints.record_success(&url, started.elapsed()),
+                Err(e) => {
+                    error!(
+                        "Error: {}\n when checking the playground endpoint {}",
+                        e, url
+                    );
+                    state
+                        .endpoints
+                        .record_failure(&url, started.elapsed(), &config.playground);
+                }
+            }
+        }
+        for metrics in state.endpoints.metrics(&config.playground.urls()) {
+            debug!("Playground endpoint metrics: {:?}", metrics);
+        }
+        sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ready() {
+        let state = BotState::new(Config::default()).unwrap();
+        assert!(!state.is_ready());
+        state.metrics.set_slack_connected(true);
+        assert!(state.is_ready());
+
+        let config = state.config.current();
+        for _ in 0..config.playground.failure_threshold {
+            state.endpoints.record_failure(
+                config.playground_url(),
+                Duration::from_millis(1),
+                &config.playground,
+            );
+        }
+        assert!(!state.is_ready());
+
+        state
+            .endpoints
+            .record_success(config.playground_url(), Duration::from_millis(1));
+        state.metrics.set_slack_connected(false);
+        assert!(!state.is_ready());
+    }
+}
@@ -0,0 +1,111 @@
+use httpmock::prelude::*;
+use rstest::*;
+use rusty_slackbot::config::CratesIoConfig;
+use rusty_slackbot::crates_io::CratesIoClient;
+use rusty_slackbot::error::RustyBotError;
+use serde_json::json;
+use serde_json::Value;
+use std::fs;
+
+#[fixture]
+fn serde_response() -> Value {
+    let s = fs::read_to_string("tests/data/crates_io_response_serde.json")
+        .expect("Error: serde_response read json file failed in tests");
+    serde_json::from_str(&s).expect("Failed parsing json in serde_response in tests")
+}
+
+fn test_client() -> CratesIoClient {
+    CratesIoClient::new(&CratesIoConfig::default()).unwrap()
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_fetch_crate(serde_response: Value) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/api/v1/crates/serde");
+        then.status(200)
+            .header("content-type", "application/json")
+            .json_body(serde_response);
+    });
+
+    let krate = test_client()
+        .fetch_crate(&server.base_url(), "serde")
+        .await
+        .expect("Failed to fetch the crate in test_fetch_crate");
+
+    mock.assert();
+    assert_eq!(krate.name, "serde");
+    assert_eq!(
+        krate.description.as_deref(),
+        Some("A generic serialization/deserialization framework")
+    );
+    assert_eq!(krate.latest_version(), "1.0.136");
+    assert_eq!(krate.downloads, 180362937);
+    assert_eq!(krate.recent_downloads, Some(24839466));
+    assert_eq!(
+        krate.repository.as_deref(),
+        Some("https://github.com/serde-rs/serde")
+    );
+    assert_eq!(krate.docs_url(), "https://docs.rs/serde/1.0.136");
+    assert_eq!(krate.cargo_toml_line(), "serde = \"1.0.136\"");
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_fetch_crate_user_agent(serde_response: Value) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/api/v1/crates/serde")
+            .header("user-agent", "test-bot (ops@example.com)");
+        then.status(200).json_body(serde_response);
+    });
+    let client = CratesIoClient::new(&CratesIoConfig {
+        user_agent: "test-bot (ops@example.com)".to_owned(),
+        ..CratesIoConfig::default()
+    })
+    .expect("Failed to build the client in test_fetch_crate_user_agent");
+
+    let result = client.fetch_crate(&server.base_url(), "serde").await;
+
+    mock.assert();
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_fetch_crate_not_found() {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/api/v1/crates/no-such-crate");
+        then.status(404)
+            .json_body(json!({"errors": [{"detail": "Not Found"}]}));
+    });
+
+    let result = test_client()
+        .fetch_crate(&server.base_url(), "no-such-crate")
+        .await;
+
+    mock.assert();
+    match result {
+        Err(RustyBotError::CrateNotFound { name }) => assert_eq!(name, "no-such-crate"),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[rstest]
+#[case(500, "Internal Server Error")]
+#[case(200, "not json")]
+#[tokio::test]
+async fn test_fetch_crate_unavailable(#[case] status: u16, #[case] body: &str) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/api/v1/crates/serde");
+        then.status(status).body(body);
+    });
+
+    let result = test_client().fetch_crate(&server.base_url(), "serde").await;
+
+    mock.assert();
+    assert!(matches!(result, Err(RustyBotError::CratesIoUnavailable(_))));
+}
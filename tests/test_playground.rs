@@ -1,9 +1,15 @@
 use httpmock::prelude::*;
 use rstest::*;
-use rusty_slackbot::playground::{PlaygroundRequest, PlaygroundResponse, ShareResponse};
+use rusty_slackbot::config::PlaygroundConfig;
+use rusty_slackbot::error::RustyBotError;
+use rusty_slackbot::playground::{
+    parse_gist_link, split_eval_result, split_trailing_expression, GistLink, PlaygroundClient,
+    PlaygroundRequest, PlaygroundResponse, ShareResponse, EVAL_RESULT_MARKER,
+};
 use serde_json::json;
 use serde_json::Value;
 use std::fs;
+use std::time::Duration;
 
 #[fixture]
 fn good_eval_response() -> Value {
@@ -36,6 +42,20 @@ fn share_link_response() -> Value {
     serde_json::from_str(&s).expect("Failed parsing json in share_link_response in tests")
 }
 
+#[fixture]
+fn crates_response() -> Value {
+    let s = fs::read_to_string("tests/data/pg_response_crates.json")
+        .expect("Error: crates_response read json file failed in tests");
+    serde_json::from_str(&s).expect("Failed parsing json in crates_response in tests")
+}
+
+#[fixture]
+fn versions_response() -> Value {
+    let s = fs::read_to_string("tests/data/pg_response_versions.json")
+        .expect("Error: versions_response read json file failed in tests");
+    serde_json::from_str(&s).expect("Failed parsing json in versions_response in tests")
+}
+
 #[rstest]
 #[case(good_code(), good_eval_response())]
 #[case(bad_code(), bad_eval_response())]
@@ -63,7 +83,10 @@ async fn test_eval_execute(#[case] code: String, #[case] raw_response: Value) {
         then.status(200).json_body(raw_response);
     });
     let request = PlaygroundRequest::new_eval(code).escape_html();
-    let response = request.execute(&server.base_url()).await.unwrap();
+    let response = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap();
 
     mock.assert();
     assert_eq!(response.status_code, "200".to_owned());
@@ -96,7 +119,10 @@ async fn test_create_share_link(good_code: String, share_link_response: Value) {
             .json_body(payload);
         then.status(200).json_body(share_link_response);
     });
-    let response = request.create_share_link(&server.base_url()).await.unwrap();
+    let response = test_client()
+        .create_share_link(&server.base_url(), &request)
+        .await
+        .unwrap();
 
     mock.assert();
     assert_eq!(
@@ -109,3 +135,373 @@ async fn test_create_share_link(good_code: String, share_link_response: Value) {
         response
     );
 }
+
+#[rstest]
+#[case("let x = 5;\nx * 2", "let x = 5;", Some("\nx * 2"))]
+#[case("let s = \"a;b\";\ns.len()", "let s = \"a;b\";", Some("\ns.len()"))]
+#[case("let c = ';';\nc", "let c = ';';", Some("\nc"))]
+#[case("let a = 1;\na &lt; 2", "let a = 1;", Some("\na &lt; 2"))]
+#[case("println!(\"Hello World\");", "println!(\"Hello World\");", None)]
+#[case("println!(\"Hello World\"", "println!(\"Hello World\"", None)]
+#[case(
+    "for i in 0..3 { println!(\"{}\", i); }",
+    "for i in 0..3 { println!(\"{}\", i); }",
+    None
+)]
+#[case("if true { 1 } else { 2 }", "if true { 1 } else { 2 }", None)]
+#[case("let x = 1; // done", "let x = 1; // done", None)]
+fn test_split_trailing_expression(
+    #[case] code: &str,
+    #[case] expected_statements: &str,
+    #[case] expected_expression: Option<&str>,
+) {
+    let (statements, expression) = split_trailing_expression(code);
+    assert_eq!(statements, expected_statements);
+    assert_eq!(expression, expected_expression);
+}
+
+#[test]
+fn test_new_eval_prints_trailing_expression() {
+    let request = PlaygroundRequest::new_eval("let v = vec![1, 2];\nv.len()".to_owned());
+    let code = request.get_code();
+    assert!(code.contains(
+        "fn main() {let v = vec![1, 2];\n(&__RustyValue(\n\nv.len()\n)).__rusty_print();\n}"
+    ));
+}
+
+#[test]
+fn test_split_eval_result() {
+    let stdout = format!("some output\n{}Some(\n    3,\n)\n", EVAL_RESULT_MARKER);
+    let (output, result) = split_eval_result(&stdout);
+    assert_eq!(output, "some output\n");
+    assert_eq!(result, Some("Some(\n    3,\n)".to_owned()));
+
+    let (output, result) = split_eval_result("Hello World\n");
+    assert_eq!(output, "Hello World\n");
+    assert!(result.is_none());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_fetch_crates(crates_response: Value) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/meta/crates");
+        then.status(200).json_body(crates_response);
+    });
+    let response = test_client()
+        .fetch_crates(&server.base_url())
+        .await
+        .unwrap();
+
+    mock.assert();
+    assert_eq!(response.crates.len(), 3);
+    assert_eq!(response.crates[2].id, "serde_json".to_owned());
+    assert_eq!(response.crates[2].version, "1.0.74".to_owned());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_fetch_versions(versions_response: Value) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/meta/versions");
+        then.status(200).json_body(versions_response);
+    });
+    let response = test_client()
+        .fetch_versions(&server.base_url())
+        .await
+        .unwrap();
+
+    mock.assert();
+    assert_eq!(response.stable.rustc.version, "1.58.1".to_owned());
+    assert!(response.stable.miri.is_none());
+    assert_eq!(response.nightly.miri.unwrap().version, "0.1.0".to_owned());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_fetch_gist(share_link_response: Value) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/meta/gist/101");
+        then.status(200).json_body(share_link_response);
+    });
+    let response = test_client()
+        .fetch_gist(&server.base_url(), "101")
+        .await
+        .unwrap();
+
+    mock.assert();
+    assert_eq!(response.id, "101".to_owned());
+    assert_eq!(
+        response.code,
+        "fn main() {println!(\"Hello World\");}".to_owned()
+    );
+}
+
+#[rstest]
+#[case(
+    "<https://play.rust-lang.org/?version=nightly&amp;mode=release&amp;edition=2018&amp;gist=abc123>",
+    Some(GistLink {
+        id: "abc123".to_owned(),
+        channel: Some("nightly".to_owned()),
+        mode: Some("release".to_owned()),
+        edition: Some("2018".to_owned()),
+    })
+)]
+#[case(
+    "abc123",
+    Some(GistLink { id: "abc123".to_owned(), channel: None, mode: None, edition: None })
+)]
+#[case("https://play.rust-lang.org/?version=stable", None)]
+#[case("not a gist!", None)]
+fn test_parse_gist_link(#[case] input: &str, #[case] expected: Option<GistLink>) {
+    assert_eq!(parse_gist_link(input), expected);
+}
+
+#[test]
+fn test_request_settings() {
+    let request = PlaygroundRequest::new("fn main() {}".to_owned())
+        .with_channel("nightly")
+        .and_then(|request| request.with_mode("release"))
+        .and_then(|request| request.with_edition("2018"))
+        .unwrap();
+    assert_eq!(
+        request.share_link("101"),
+        "https://play.rust-lang.org/?version=nightly&mode=release&edition=2018&gist=101"
+    );
+    assert!(PlaygroundRequest::new("".to_owned())
+        .with_channel("unstable")
+        .is_err());
+}
+
+#[test]
+fn test_cache_key() {
+    let request = PlaygroundRequest::new("fn main() {\n    println!(\"hi\");\n}".to_owned());
+    let same_code =
+        PlaygroundRequest::new("\nfn main() {  \n    println!(\"hi\");\n}\n".to_owned());
+    assert_eq!(request.cache_key(), same_code.cache_key());
+
+    let other_mode = request.clone().with_mode("release").unwrap();
+    assert_ne!(request.cache_key(), other_mode.cache_key());
+    let other_code = PlaygroundRequest::new("fn main() {}".to_owned());
+    assert_ne!(request.cache_key(), other_code.cache_key());
+}
+
+fn test_client() -> PlaygroundClient {
+    PlaygroundClient::new(&PlaygroundConfig {
+        request_timeout_secs: 1,
+        max_retries: 2,
+        retry_backoff_ms: 10,
+        ..PlaygroundConfig::default()
+    })
+    .unwrap()
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_create_share_link_trailing_expression(share_link_response: Value) {
+    let request = PlaygroundRequest::new_eval("let v = vec![1, 2];\nv.len()".to_owned());
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/meta/gist/").json_body(json!({
+            "code": "fn main() {let v = vec![1, 2];\nprintln!(\"{:#?}\", v.len());\n}"
+        }));
+        then.status(200).json_body(share_link_response);
+    });
+    test_client()
+        .create_share_link(&server.base_url(), &request)
+        .await
+        .unwrap();
+
+    mock.assert();
+    assert!(request.get_code().contains("__RustyValue"));
+}
+
+#[tokio::test]
+async fn test_client_retries_server_errors() {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/meta/versions");
+        then.status(502).body("<html>Bad Gateway</html>");
+    });
+    let error = test_client()
+        .fetch_versions(&server.base_url())
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(3);
+    assert!(matches!(error, RustyBotError::PlaygroundUpstream(_)));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_client_does_not_retry_posts(good_code: String) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/execute");
+        then.status(502).body("<html>Bad Gateway</html>");
+    });
+    let request = PlaygroundRequest::new_eval(good_code);
+    let error = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(matches!(error, RustyBotError::PlaygroundUpstream(_)));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_client_malformed_response(good_code: String) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/execute");
+        then.status(200).body("not json");
+    });
+    let request = PlaygroundRequest::new_eval(good_code);
+    let error = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(matches!(
+        error,
+        RustyBotError::PlaygroundMalformedResponse(_)
+    ));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_client_timeout(good_code: String, good_eval_response: Value) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/execute");
+        then.status(200)
+            .json_body(good_eval_response)
+            .delay(Duration::from_secs(2));
+    });
+    let request = PlaygroundRequest::new_eval(good_code);
+    let error = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(matches!(error, RustyBotError::PlaygroundTimeout));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_client_rate_limited(good_code: String) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/execute");
+        then.status(429)
+            .header("Retry-After", "30")
+            .body("Too Many Requests");
+    });
+    let request = PlaygroundRequest::new_eval(good_code);
+    let error = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(matches!(
+        error,
+        RustyBotError::PlaygroundRateLimited {
+            retry_after: Some(30)
+        }
+    ));
+    assert_eq!(
+        error.user_message(),
+        "The playground is getting too many requests, please retry in 30 seconds."
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_client_error_payload(good_code: String) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/execute");
+        then.status(400)
+            .json_body(json!({"error": "The edition is not supported"}));
+    });
+    let request = PlaygroundRequest::new_eval(good_code);
+    let error = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(
+        matches!(error, RustyBotError::PlaygroundError(ref message) if message == "The edition is not supported")
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_client_error_payload_server_error(good_code: String) {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/execute");
+        then.status(500)
+            .json_body(json!({"error": "Unable to deserialize request: unknown variant `2020`"}));
+    });
+    let request = PlaygroundRequest::new_eval(good_code);
+    let error = test_client()
+        .execute(&server.base_url(), &request)
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(!error.is_endpoint_failure());
+    assert_eq!(
+        error.user_message(),
+        "The playground couldn't run this: Unable to deserialize request: unknown variant `2020`"
+    );
+}
+
+#[tokio::test]
+async fn test_client_error_payload_not_retried() {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/meta/gist/unknown");
+        then.status(500)
+            .json_body(json!({"error": "Gist not found"}));
+    });
+    let error = test_client()
+        .fetch_gist(&server.base_url(), "unknown")
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(
+        matches!(error, RustyBotError::PlaygroundError(ref message) if message == "Gist not found")
+    );
+}
+
+#[tokio::test]
+async fn test_client_client_error_status() {
+    let server = MockServer::start_async().await;
+    let mock = server.mock(|when, then| {
+        when.method(GET).path("/meta/gist/unknown");
+        then.status(404).body("<html>Not Found</html>");
+    });
+    let error = test_client()
+        .fetch_gist(&server.base_url(), "unknown")
+        .await
+        .unwrap_err();
+
+    mock.assert_hits(1);
+    assert!(matches!(
+        error,
+        RustyBotError::PlaygroundRequestRejected { status: 404 }
+    ));
+    assert!(!error.is_endpoint_failure());
+}